@@ -0,0 +1,186 @@
+use std::{
+    fs::File,
+    future::Future,
+    io,
+    os::unix::fs::FileExt,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::Result;
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    task::{self, JoinHandle},
+};
+
+use crate::{
+    error::ParcelError, parcel::ParcelView, positional::read_at_nowait, InodeKind, ParcelHandle,
+    ReaderWriter,
+};
+
+/// Most data read by one blocking task when streaming a file that isn't cached
+const CHUNK_SIZE: u64 = 256 * 1024;
+
+/// A stored parcel opened for reading from async code. Reads of cached data complete without
+/// leaving the calling task; only reads that have to wait for the disk are handed to the
+/// runtime's blocking pool. Clones share the parcel.
+#[derive(Debug, Clone)]
+pub struct AsyncParcel {
+    view: Arc<ParcelView>,
+}
+
+impl AsyncParcel {
+    /// Load a parcel from disk
+    pub async fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let view = task::spawn_blocking(move || {
+            let f = File::open(path)?;
+            ParcelHandle::load(Box::new(ReaderWriter::new(f)))?.into_view()
+        })
+        .await??;
+        Ok(Self::from(view))
+    }
+
+    /// The view being read, for looking up paths and attributes
+    pub fn view(&self) -> &ParcelView {
+        &self.view
+    }
+
+    /// Read the contents of a file
+    pub async fn read(&self, ino: u64, offset: u64, size: Option<u64>) -> Result<Vec<u8>> {
+        let (position, len) = match self.view.data_span(ino, offset, size) {
+            Some(span) => span,
+            None => {
+                let view = Arc::clone(&self.view);
+                return task::spawn_blocking(move || view.read(ino, offset, size)).await?;
+            }
+        };
+        let mut buf = vec![0u8; len as usize];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match read_at_nowait(
+                self.view.file(),
+                &mut buf[filled..],
+                position + filled as u64,
+            )? {
+                Some(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Some(read) => filled += read,
+                None => break,
+            }
+        }
+        if filled < buf.len() {
+            let view = Arc::clone(&self.view);
+            buf = task::spawn_blocking(move || -> io::Result<Vec<u8>> {
+                view.file()
+                    .read_exact_at(&mut buf[filled..], position + filled as u64)?;
+                Ok(buf)
+            })
+            .await??;
+        }
+        Ok(buf)
+    }
+
+    /// Open a file for streaming its contents
+    pub fn open(&self, ino: u64) -> Result<AsyncFile> {
+        let attr = self.view.getattr(ino).ok_or(ParcelError::Enoent)?;
+        if attr.kind != InodeKind::RegularFile {
+            return Err(ParcelError::NotFile.into());
+        }
+        Ok(AsyncFile {
+            view: Arc::clone(&self.view),
+            ino,
+            offset: 0,
+            size: attr.size,
+            chunk: Vec::new(),
+            pending: None,
+        })
+    }
+}
+
+impl From<ParcelView> for AsyncParcel {
+    fn from(view: ParcelView) -> Self {
+        Self {
+            view: Arc::new(view),
+        }
+    }
+}
+
+/// A file in a parcel being streamed to async code
+#[derive(Debug)]
+pub struct AsyncFile {
+    view:    Arc<ParcelView>,
+    ino:     u64,
+    /// Offset within the file of the next byte to return
+    offset:  u64,
+    size:    u64,
+    /// Data read by a blocking task and not yet returned
+    chunk:   Vec<u8>,
+    pending: Option<JoinHandle<Result<Vec<u8>>>>,
+}
+
+impl AsyncFile {
+    /// Try to fill `buf` from the page cache without blocking. Returns false if the data isn't
+    /// cached or isn't stored contiguously.
+    fn read_cached(&mut self, buf: &mut ReadBuf<'_>) -> io::Result<bool> {
+        let want = (buf.remaining() as u64).min(self.size - self.offset);
+        let position = match self.view.data_span(self.ino, self.offset, Some(want)) {
+            Some((position, _)) => position,
+            None => return Ok(false),
+        };
+        let unfilled = &mut buf.initialize_unfilled()[..want as usize];
+        match read_at_nowait(self.view.file(), unfilled, position)? {
+            Some(0) => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Some(read) => {
+                buf.advance(read);
+                self.offset += read as u64;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Hand the rest of `buf` from `chunk` over to the reader
+    fn drain_chunk(&mut self, buf: &mut ReadBuf<'_>) {
+        let len = buf.remaining().min(self.chunk.len());
+        buf.put_slice(&self.chunk[..len]);
+        self.chunk.drain(..len);
+    }
+}
+
+impl AsyncRead for AsyncFile {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if !this.chunk.is_empty() {
+            this.drain_chunk(buf);
+            return Poll::Ready(Ok(()));
+        }
+        if this.pending.is_none() {
+            if this.offset >= this.size || buf.remaining() == 0 || this.read_cached(buf)? {
+                return Poll::Ready(Ok(()));
+            }
+            let (view, ino, offset) = (Arc::clone(&this.view), this.ino, this.offset);
+            this.pending = Some(task::spawn_blocking(move || {
+                view.read(ino, offset, Some(CHUNK_SIZE))
+            }));
+        }
+        let pending = this.pending.as_mut().expect("No read in progress");
+        let chunk = match Pin::new(pending).poll(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(result) => result.map_err(io::Error::other)?,
+        };
+        this.pending = None;
+        this.chunk = chunk.map_err(io::Error::other)?;
+        if this.chunk.is_empty() {
+            return Poll::Ready(Err(io::Error::from(io::ErrorKind::UnexpectedEof)));
+        }
+        this.offset += this.chunk.len() as u64;
+        this.drain_chunk(buf);
+        Poll::Ready(Ok(()))
+    }
+}
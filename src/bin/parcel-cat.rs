@@ -1,4 +1,4 @@
-use std::{fs::File, io, io::Write, path::PathBuf};
+use std::{fs::File, io, path::PathBuf};
 
 use clap::{App, Arg};
 use pyxis_parcel::{ParcelHandle, ReaderWriter};
@@ -33,7 +33,5 @@ fn main() {
         .select(PathBuf::from(matches.value_of("path").unwrap()))
         .unwrap();
 
-    io::stdout()
-        .write_all(&parcel.read(ino, 0, None).unwrap())
-        .unwrap();
+    parcel.copy_to(ino, 0, None, &mut io::stdout()).unwrap();
 }
@@ -1,15 +1,29 @@
 use std::{
-    collections::BTreeMap,
-    ffi::OsString,
-    fs,
-    fs::File,
-    os::unix::fs::FileTypeExt,
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
     path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
 };
 
 use clap::{App, Arg};
-use pyxis_parcel::{FileAdd, InodeAttr, InodeKind, ParcelHandle, ReaderWriter};
-use walkdir::WalkDir;
+use pyxis_parcel::{Manifest, ParcelBuilder, ParcelHandle, ReaderWriter, BLOCK_SIZE};
+
+/// Open a tarball (or stdin for `-`), transparently decompressing gzip and zstd
+fn open_tar(path: &str) -> Box<dyn Read> {
+    let mut reader: Box<dyn BufRead> = match path {
+        "-" => Box::new(BufReader::new(io::stdin())),
+        _ => Box::new(BufReader::new(File::open(path).unwrap())),
+    };
+    let magic = reader.fill_buf().unwrap();
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Box::new(flate2::read::GzDecoder::new(reader))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Box::new(zstd::Decoder::with_buffer(reader).unwrap())
+    } else {
+        Box::new(reader)
+    }
+}
 
 fn main() {
     let matches = App::new("Parcel-Create")
@@ -26,132 +40,210 @@ fn main() {
         .arg(
             Arg::new("input")
                 .value_name("INPUT")
-                .help("The input directory to scan")
+                .help("The input directory to scan (or tarball, with --from-tar)")
                 .multiple_occurrences(true)
                 .takes_value(true)
                 .required(true),
         )
+        .arg(
+            Arg::new("at")
+                .long("at")
+                .value_name("DST")
+                .help("Place the contents of the input directories after this at DST in the parcel")
+                .multiple_occurrences(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("from-tar")
+                .long("from-tar")
+                .help("Read each INPUT as a .tar, .tar.gz or .tar.zst archive (- for stdin)"),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .value_name("N")
+                .help("Number of worker threads scanning input files (default: one per CPU)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .help(
+                    "Leave out matching paths and everything beneath them. \
+                     Globs with a / match paths within INPUT, others match file names",
+                )
+                .multiple_occurrences(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .value_name("GLOB")
+                .help(
+                    "Keep matching paths even if they or a directory above them match --exclude. \
+                     Excluded directories are kept to hold them, without their other contents",
+                )
+                .multiple_occurrences(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("one-file-system")
+                .long("one-file-system")
+                .help("Don't descend into directories on other filesystems"),
+        )
+        .arg(
+            Arg::new("reproducible")
+                .long("reproducible")
+                .help("Shorthand for --clamp-times --zero-owner --normalize-perms --sort --exclude-volatile-xattrs"),
+        )
+        .arg(
+            Arg::new("clamp-times")
+                .long("clamp-times")
+                .help("Clamp timestamps to $SOURCE_DATE_EPOCH"),
+        )
+        .arg(
+            Arg::new("zero-owner")
+                .long("zero-owner")
+                .help("Make everything owned by uid and gid 0"),
+        )
+        .arg(
+            Arg::new("normalize-perms")
+                .long("normalize-perms")
+                .help("Use 0755 for directories and executables and 0644 for other files"),
+        )
+        .arg(
+            Arg::new("sort")
+                .long("sort")
+                .help("Number inodes in sorted path order rather than directory listing order"),
+        )
+        .arg(
+            Arg::new("exclude-xattr")
+                .long("exclude-xattr")
+                .value_name("PREFIX")
+                .help("Leave out xattrs whose names start with PREFIX")
+                .multiple_occurrences(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("exclude-volatile-xattrs")
+                .long("exclude-volatile-xattrs")
+                .help("Leave out xattrs that vary between build hosts, such as SELinux labels"),
+        )
+        .arg(
+            Arg::new("align")
+                .long("align")
+                .help("Align file data to filesystem blocks, so it can be reflinked or mapped"),
+        )
+        .arg(
+            Arg::new("manifest")
+                .long("manifest")
+                .value_name("FILE")
+                .help("Override ownership and permissions, or create device nodes, per path")
+                .multiple_occurrences(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .help("Mark a path in the parcel as a config file")
+                .multiple_occurrences(true)
+                .takes_value(true),
+        )
         .get_matches();
 
     let mut parcel: ParcelHandle = ParcelHandle::new();
+    if matches.is_present("align") {
+        parcel.set_alignment(Some(BLOCK_SIZE));
+    }
 
-    let mut dir_map: BTreeMap<PathBuf, u64> = BTreeMap::new();
-    dir_map.insert(PathBuf::from("/"), 1);
+    let mut builder = ParcelBuilder::new();
+    if let Some(threads) = matches.value_of("threads") {
+        builder = builder.threads(threads.parse().unwrap());
+    }
+    let reproducible = matches.is_present("reproducible");
+    if reproducible || matches.is_present("clamp-times") {
+        let epoch: u64 = env::var("SOURCE_DATE_EPOCH")
+            .expect("SOURCE_DATE_EPOCH must be set to clamp times")
+            .parse()
+            .unwrap();
+        builder = builder.clamp_time(UNIX_EPOCH + Duration::from_secs(epoch));
+    }
+    builder = builder
+        .zero_owner(reproducible || matches.is_present("zero-owner"))
+        .normalize_perms(reproducible || matches.is_present("normalize-perms"))
+        .sorted(reproducible || matches.is_present("sort"));
+    if reproducible || matches.is_present("exclude-volatile-xattrs") {
+        builder = builder.exclude_volatile_xattrs();
+    }
+    for prefix in matches.values_of("exclude-xattr").unwrap_or_default() {
+        builder = builder.exclude_xattr(prefix);
+    }
+    for glob in matches.values_of("exclude").unwrap_or_default() {
+        builder = builder.exclude(glob);
+    }
+    for glob in matches.values_of("include").unwrap_or_default() {
+        builder = builder.include(glob);
+    }
+    builder = builder.one_file_system(matches.is_present("one-file-system"));
 
-    for input in matches.values_of("input").unwrap() {
-        for entry in WalkDir::new(input).min_depth(1) {
-            let entry = entry.unwrap();
-            let entry_path = Path::new("/").join(entry.path().strip_prefix(input).unwrap());
-            let parent_inode = *dir_map.get(entry_path.parent().unwrap()).unwrap();
-            let entry_name = entry_path.file_name().unwrap();
+    // Each --at applies to the inputs that follow it on the command line
+    let at: Vec<(usize, &str)> = matches
+        .indices_of("at")
+        .into_iter()
+        .flatten()
+        .zip(matches.values_of("at").into_iter().flatten())
+        .collect();
+    let inputs = matches
+        .indices_of("input")
+        .unwrap()
+        .zip(matches.values_of("input").unwrap());
+    for (index, input) in inputs {
+        if matches.is_present("from-tar") {
+            parcel.import_tar(open_tar(input)).unwrap();
+        } else {
+            let dst = at
+                .iter()
+                .rev()
+                .find(|(at, _)| *at < index)
+                .map_or("/", |(_, dst)| *dst);
+            builder
+                .add_dir_at(&mut parcel, Path::new(input), Path::new(dst))
+                .unwrap();
+        }
+    }
 
-            let meta = entry.metadata().unwrap();
-            let file_type = meta.file_type();
+    for path in matches.values_of("manifest").unwrap_or_default() {
+        let manifest = Manifest::parse(&fs::read_to_string(path).unwrap()).unwrap();
+        parcel.apply_manifest(&manifest).unwrap();
+    }
 
-            if file_type.is_file() {
-                let attrs = InodeAttr::from_meta(&meta);
-                let mut xattrs: BTreeMap<OsString, Vec<u8>> = BTreeMap::new();
-                for attr in xattr::list(entry.path()).unwrap() {
-                    xattrs.insert(
-                        attr.clone(),
-                        xattr::get(entry.path(), attr.clone()).unwrap().unwrap(),
-                    );
-                }
-                let ino = parcel
-                    .add_file(
-                        FileAdd::Name(entry.path().as_os_str().to_os_string()),
-                        attrs,
-                        xattrs,
-                    )
-                    .unwrap();
-                dir_map.insert(entry_path.clone(), ino);
-                parcel
-                    .insert_dirent(
-                        parent_inode,
-                        entry_name.to_os_string(),
-                        ino,
-                        InodeKind::RegularFile,
-                    )
-                    .unwrap();
-            } else if file_type.is_dir() {
-                let attrs = InodeAttr::from_meta(&meta);
-                let mut xattrs: BTreeMap<OsString, Vec<u8>> = BTreeMap::new();
-                for attr in xattr::list(entry.path()).unwrap() {
-                    xattrs.insert(
-                        attr.clone(),
-                        xattr::get(entry.path(), attr.clone()).unwrap().unwrap(),
-                    );
-                }
-                let ino = parcel.add_directory(attrs, xattrs);
-                dir_map.insert(entry_path.clone(), ino);
-                parcel
-                    .insert_dirent(
-                        parent_inode,
-                        entry_name.to_os_string(),
-                        ino,
-                        InodeKind::Directory,
-                    )
-                    .unwrap();
-            } else if file_type.is_symlink() {
-                let attrs = InodeAttr::from_meta(&meta);
-                let mut xattrs: BTreeMap<OsString, Vec<u8>> = BTreeMap::new();
-                for attr in xattr::list(entry.path()).unwrap() {
-                    xattrs.insert(
-                        attr.clone(),
-                        xattr::get(entry.path(), attr.clone()).unwrap().unwrap(),
-                    );
-                }
-                let ino = parcel
-                    .add_symlink(
-                        fs::read_link(entry.path())
-                            .unwrap()
-                            .as_os_str()
-                            .to_os_string(),
-                        attrs,
-                        xattrs,
-                    )
-                    .unwrap();
-                dir_map.insert(entry_path.clone(), ino);
-                parcel
-                    .insert_dirent(
-                        parent_inode,
-                        entry_name.to_os_string(),
-                        ino,
-                        InodeKind::Symlink,
-                    )
-                    .unwrap();
-            } else if file_type.is_char_device() {
-                let attrs = InodeAttr::from_meta(&meta);
-                let mut xattrs: BTreeMap<OsString, Vec<u8>> = BTreeMap::new();
-                for attr in xattr::list(entry.path()).unwrap() {
-                    xattrs.insert(
-                        attr.clone(),
-                        xattr::get(entry.path(), attr.clone()).unwrap().unwrap(),
-                    );
-                }
-                let ino = parcel.add_char(attrs, xattrs);
-                dir_map.insert(entry_path.clone(), ino);
-                parcel
-                    .insert_dirent(
-                        parent_inode,
-                        entry_name.to_os_string(),
-                        ino,
-                        InodeKind::CharDevice,
-                    )
-                    .unwrap();
-            } else if file_type.is_block_device() {
-                unimplemented!("Block device");
-            } else if file_type.is_fifo() {
-                unimplemented!("FIFO");
-            } else if file_type.is_socket() {
-                unimplemented!("Socket");
-            } else {
-                panic!("Unknown file type: {:?}", file_type);
-            }
-        }
+    for path in matches.values_of("config").unwrap_or_default() {
+        parcel.mark_config(PathBuf::from(path)).unwrap();
     }
 
-    let outfile = File::create(matches.value_of("output").unwrap()).unwrap();
-    parcel.set_file(Box::new(ReaderWriter::new(outfile)));
+    // Only empty the output once it is locked, so a parcel in use elsewhere is left alone. The
+    // clone stays open until exit, as closing it would give up the lock.
+    let outfile = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(matches.value_of("output").unwrap())
+        .unwrap();
+    let truncate = outfile.try_clone().unwrap();
+    parcel
+        .set_file(Box::new(ReaderWriter::new(outfile)))
+        .unwrap();
+    truncate.set_len(0).unwrap();
     parcel.store().unwrap();
+
+    let savings = parcel.dedup_savings();
+    if savings.files > 0 {
+        println!(
+            "Deduplicated {} files, saving {} bytes",
+            savings.files, savings.bytes
+        );
+    }
 }
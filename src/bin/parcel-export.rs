@@ -0,0 +1,71 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::PathBuf,
+};
+
+use clap::{App, Arg, ArgGroup};
+use pyxis_parcel::{ParcelHandle, ReaderWriter};
+
+fn main() {
+    let matches = App::new("Parcel-Export")
+        .version("0.1.0")
+        .author("chordtoll <git@chordtoll.com>")
+        .about("Writes the contents of a parcel to stdout in another archive format")
+        .arg(
+            Arg::new("tar")
+                .long("tar")
+                .help("Write a POSIX (PAX) tar stream"),
+        )
+        .arg(
+            Arg::new("cpio")
+                .long("cpio")
+                .help("Write a newc cpio archive, e.g. for an initramfs"),
+        )
+        .group(
+            ArgGroup::new("format")
+                .args(&["tar", "cpio"])
+                .required(true),
+        )
+        .arg(
+            Arg::new("overlay")
+                .long("overlay")
+                .value_name("PARCEL")
+                .help("Layer another parcel on top of PARCEL (cpio only)")
+                .multiple_occurrences(true)
+                .takes_value(true)
+                .requires("cpio"),
+        )
+        .arg(
+            Arg::new("parcel")
+                .value_name("PARCEL")
+                .help("The parcel to export")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::new("path")
+                .value_name("PATH")
+                .help("The directory within the parcel to export")
+                .takes_value(true)
+                .default_value("/"),
+        )
+        .get_matches();
+
+    let mut layers: Vec<ParcelHandle> = std::iter::once(matches.value_of("parcel").unwrap())
+        .chain(matches.values_of("overlay").unwrap_or_default())
+        .map(|p| {
+            let f = File::open(p).unwrap();
+            ParcelHandle::load(Box::new(ReaderWriter::new(f))).unwrap()
+        })
+        .collect();
+    let path = PathBuf::from(matches.value_of("path").unwrap());
+    let stdout = io::stdout();
+    let out = BufWriter::new(stdout.lock());
+
+    if matches.is_present("tar") {
+        layers[0].export_tar(path, out).unwrap();
+    } else if matches.is_present("cpio") {
+        ParcelHandle::export_cpio_overlay(&mut layers, path, out).unwrap();
+    }
+}
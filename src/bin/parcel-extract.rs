@@ -0,0 +1,59 @@
+use std::{fs::File, path::Path};
+
+use clap::{App, Arg};
+use pyxis_parcel::{ConfigPolicy, ParcelHandle, ReaderWriter};
+
+fn main() {
+    let matches = App::new("Parcel-Extract")
+        .version("0.1.0")
+        .author("chordtoll <git@chordtoll.com>")
+        .about("Extracts the contents of a parcel onto the filesystem")
+        .arg(
+            Arg::new("parcel")
+                .value_name("PARCEL")
+                .help("The parcel to extract")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::new("root")
+                .value_name("ROOT")
+                .help("The directory to extract into")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::new("config-policy")
+                .long("config-policy")
+                .value_name("POLICY")
+                .help("How to handle config files that already exist")
+                .possible_values(["keep", "new", "replace-unmodified"])
+                .default_value("new")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("previous")
+                .long("previous")
+                .value_name("PARCEL")
+                .help("The previously installed parcel, for replace-unmodified")
+                .takes_value(true)
+                .required_if_eq("config-policy", "replace-unmodified"),
+        )
+        .get_matches();
+
+    let policy = match matches.value_of("config-policy").unwrap() {
+        "keep" => ConfigPolicy::KeepExisting,
+        "new" => ConfigPolicy::WriteNew,
+        _ => {
+            let f = File::open(matches.value_of("previous").unwrap()).unwrap();
+            let mut previous = ParcelHandle::load(Box::new(ReaderWriter::new(f))).unwrap();
+            ConfigPolicy::ReplaceUnmodified(previous.config_digests().unwrap())
+        }
+    };
+
+    let f = File::open(matches.value_of("parcel").unwrap()).unwrap();
+    let mut parcel = ParcelHandle::load(Box::new(ReaderWriter::new(f))).unwrap();
+    parcel
+        .extract(Path::new(matches.value_of("root").unwrap()), &policy)
+        .unwrap();
+}
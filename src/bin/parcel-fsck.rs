@@ -0,0 +1,53 @@
+use std::{fs::File, process};
+
+use clap::{App, Arg};
+use pyxis_parcel::{ParcelHandle, ReaderWriter};
+
+fn main() {
+    let matches = App::new("Parcel-Fsck")
+        .version("0.1.0")
+        .author("chordtoll <git@chordtoll.com>")
+        .about("Checks a parcel's inodes for consistency, and optionally repairs them")
+        .arg(
+            Arg::new("repair")
+                .long("repair")
+                .help("Repair what can be repaired, moving unreachable inodes to /lost+found"),
+        )
+        .arg(
+            Arg::new("parcel")
+                .value_name("PARCEL")
+                .help("The parcel to check")
+                .takes_value(true)
+                .required(true),
+        )
+        .get_matches();
+
+    let repair = matches.is_present("repair");
+    let f = File::options()
+        .read(true)
+        .write(repair)
+        .open(matches.value_of("parcel").unwrap())
+        .unwrap();
+    let mut parcel = ParcelHandle::load(Box::new(ReaderWriter::new(f))).unwrap();
+
+    let found = parcel.check().unwrap();
+    for violation in found.iter() {
+        println!("{}", violation);
+    }
+    let left = match repair && !found.is_empty() {
+        true => {
+            let left = parcel.repair().unwrap();
+            parcel.store().unwrap();
+            println!(
+                "Repaired {} of {} problems",
+                found.len().saturating_sub(left.len()),
+                found.len()
+            );
+            left
+        }
+        false => found,
+    };
+    if !left.is_empty() {
+        process::exit(1);
+    }
+}
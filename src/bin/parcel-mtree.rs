@@ -0,0 +1,42 @@
+use std::{fs::File, io, path::Path, process};
+
+use clap::{App, Arg};
+use pyxis_parcel::{ParcelHandle, ReaderWriter};
+
+fn main() {
+    let matches = App::new("Parcel-Mtree")
+        .version("0.1.0")
+        .author("chordtoll <git@chordtoll.com>")
+        .about("Prints a BSD mtree manifest of a parcel, or checks an installed root against it")
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .value_name("ROOT")
+                .help("Report where the parcel's contents installed under ROOT have changed")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("parcel")
+                .value_name("PARCEL")
+                .help("The parcel to describe")
+                .takes_value(true)
+                .required(true),
+        )
+        .get_matches();
+
+    let f = File::open(matches.value_of("parcel").unwrap()).unwrap();
+    let mut parcel = ParcelHandle::load(Box::new(ReaderWriter::new(f))).unwrap();
+
+    match matches.value_of("check") {
+        Some(root) => {
+            let drift = parcel.check_installed(Path::new(root)).unwrap();
+            for d in drift.iter() {
+                println!("{}", d);
+            }
+            if !drift.is_empty() {
+                process::exit(1);
+            }
+        }
+        None => parcel.write_mtree(io::stdout().lock()).unwrap(),
+    }
+}
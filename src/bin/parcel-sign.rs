@@ -0,0 +1,68 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+};
+
+use clap::{App, Arg};
+use pyxis_parcel::{ParcelHandle, ReaderWriter, SigningKey};
+
+fn main() {
+    let matches = App::new("Parcel-Sign")
+        .version("0.1.0")
+        .author("chordtoll <git@chordtoll.com>")
+        .about("Signs a parcel with an Ed25519 key")
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .value_name("KEY")
+                .help("Hex-encoded secret key file")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::new("generate")
+                .long("generate")
+                .help("Generate a new keypair at KEY and KEY.pub instead of signing"),
+        )
+        .arg(
+            Arg::new("parcel")
+                .value_name("PARCEL")
+                .help("The parcel to sign")
+                .takes_value(true)
+                .required_unless_present("generate"),
+        )
+        .get_matches();
+
+    let key_path = matches.value_of("key").unwrap();
+
+    if matches.is_present("generate") {
+        let mut seed = [0u8; 32];
+        File::open("/dev/urandom")
+            .unwrap()
+            .read_exact(&mut seed)
+            .unwrap();
+        let key = SigningKey::from_bytes(&seed);
+        fs::write(key_path, hex::encode(key.to_bytes()) + "\n").unwrap();
+        fs::write(
+            String::from(key_path) + ".pub",
+            hex::encode(key.verifying_key().to_bytes()) + "\n",
+        )
+        .unwrap();
+        return;
+    }
+
+    let seed: [u8; 32] = hex::decode(fs::read_to_string(key_path).unwrap().trim())
+        .unwrap()
+        .try_into()
+        .expect("Secret key must be 32 bytes");
+    let key = SigningKey::from_bytes(&seed);
+
+    let f = File::options()
+        .read(true)
+        .write(true)
+        .open(matches.value_of("parcel").unwrap())
+        .unwrap();
+    let mut parcel = ParcelHandle::load(Box::new(ReaderWriter::new(f))).unwrap();
+    parcel.sign(&key).unwrap();
+    parcel.store().unwrap();
+}
@@ -0,0 +1,43 @@
+use std::fs::{self, File};
+
+use clap::{App, Arg};
+use pyxis_parcel::{ParcelHandle, ReaderWriter};
+
+fn main() {
+    let matches = App::new("Parcel-Upgrade")
+        .version("0.1.0")
+        .author("chordtoll <git@chordtoll.com>")
+        .about("Rewrites a parcel from an earlier format version in the current format")
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("OUTPUT")
+                .help("Write the upgraded parcel here instead of upgrading in place")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("parcel")
+                .value_name("PARCEL")
+                .help("The parcel to upgrade")
+                .takes_value(true)
+                .required(true),
+        )
+        .get_matches();
+
+    let mut path = matches.value_of("parcel").unwrap();
+    if let Some(output) = matches.value_of("output") {
+        fs::copy(path, output).unwrap();
+        path = output;
+    }
+
+    let f = File::options().read(true).write(true).open(path).unwrap();
+    let mut parcel = ParcelHandle::load(Box::new(ReaderWriter::new(f))).unwrap();
+    match parcel.upgraded_from() {
+        Some(version) => {
+            parcel.store().unwrap();
+            println!("Upgraded from version {}", version);
+        }
+        None => println!("Already current"),
+    }
+}
@@ -0,0 +1,46 @@
+use std::{fs, fs::File, process};
+
+use clap::{App, Arg};
+use pyxis_parcel::{ParcelHandle, ReaderWriter, VerifyingKey};
+
+fn main() {
+    let matches = App::new("Parcel-Verify")
+        .version("0.1.0")
+        .author("chordtoll <git@chordtoll.com>")
+        .about("Checks a parcel's signature and file digests")
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .value_name("KEY")
+                .help("Hex-encoded public key file")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::new("parcel")
+                .value_name("PARCEL")
+                .help("The parcel to verify")
+                .takes_value(true)
+                .required(true),
+        )
+        .get_matches();
+
+    let public: [u8; 32] = hex::decode(
+        fs::read_to_string(matches.value_of("key").unwrap())
+            .unwrap()
+            .trim(),
+    )
+    .unwrap()
+    .try_into()
+    .expect("Public key must be 32 bytes");
+    let key = VerifyingKey::from_bytes(&public).unwrap();
+
+    let f = File::open(matches.value_of("parcel").unwrap()).unwrap();
+    match ParcelHandle::load_verified(Box::new(ReaderWriter::new(f)), &key) {
+        Ok(_) => println!("OK"),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
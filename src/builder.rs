@@ -0,0 +1,443 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ffi::OsString,
+    fs::{self, File, Metadata},
+    iter,
+    num::NonZeroUsize,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use glob::{MatchOptions, Pattern};
+use lexiclean::Lexiclean;
+use walkdir::{DirEntry, WalkDir};
+
+use crate::{error::ParcelError, signing, FileAdd, InodeAttr, InodeKind, ParcelHandle};
+
+/// Number of entries scanned per worker before results are added to the parcel.
+/// Bounds how far the walk runs ahead of the (single-threaded) parcel updates.
+const BATCH_PER_THREAD: usize = 256;
+
+/// Xattrs that differ between otherwise identical trees: security labels and integrity
+/// signatures assigned by the build host, and desktop download bookkeeping
+const VOLATILE_XATTRS: &[&str] = &[
+    "security.selinux",
+    "security.ima",
+    "security.evm",
+    "user.xdg.",
+];
+
+/// Globs match within a single path component unless they use `**`
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive:              true,
+    require_literal_separator:   true,
+    require_literal_leading_dot: false,
+};
+
+/// Builds parcels from directory trees, scanning files on a pool of worker threads.
+/// Entries are always added in walk order, so the output doesn't depend on the thread count.
+#[derive(Debug, Clone)]
+pub struct ParcelBuilder {
+    threads:         usize,
+    clamp_time:      Option<SystemTime>,
+    zero_owner:      bool,
+    normalize_perms: bool,
+    sorted:          bool,
+    exclude_xattrs:  Vec<String>,
+    exclude:         Vec<String>,
+    include:         Vec<String>,
+    one_file_system: bool,
+}
+
+/// An object found by the directory walk
+struct Entry {
+    /// Where the object is on disk
+    source: PathBuf,
+    /// Where the object goes in the parcel
+    path:   PathBuf,
+    meta:   Metadata,
+}
+
+/// What a worker learned about an entry
+enum Scanned {
+    File(Option<String>),
+    Directory,
+    Symlink(OsString),
+    Char,
+}
+
+impl Scanned {
+    fn kind(&self) -> InodeKind {
+        match self {
+            Scanned::File(_) => InodeKind::RegularFile,
+            Scanned::Directory => InodeKind::Directory,
+            Scanned::Symlink(_) => InodeKind::Symlink,
+            Scanned::Char => InodeKind::CharDevice,
+        }
+    }
+}
+
+/// Compile filter globs. Globs containing `/` match the whole path beneath the input
+/// (and are anchored at its root); others match the file name at any depth.
+fn compile_globs(globs: &[String]) -> Result<Vec<Pattern>> {
+    Ok(globs
+        .iter()
+        .map(|glob| match glob.contains('/') && !glob.starts_with('/') {
+            true => Pattern::new(&format!("/{}", glob)),
+            false => Pattern::new(glob),
+        })
+        .collect::<Result<Vec<Pattern>, _>>()?)
+}
+
+/// Whether `path` is added: the deepest of it and its ancestors that matches an include or an
+/// exclude decides, with includes winning at the same depth
+fn kept(exclude: &[Pattern], include: &[Pattern], path: &Path) -> bool {
+    let mut ancestors: Vec<&Path> = path.ancestors().collect();
+    ancestors.pop();
+    ancestors.iter().rev().fold(true, |kept, path| {
+        match (any_match(include, path), any_match(exclude, path)) {
+            (true, _) => true,
+            (false, true) => false,
+            (false, false) => kept,
+        }
+    })
+}
+
+/// Whether a glob could match something beneath the directory `dir`
+fn could_match_below(pattern: &Pattern, dir: &Path) -> bool {
+    if !pattern.as_str().contains('/') {
+        return true;
+    }
+    let mut globs = pattern.as_str().split('/').skip(1);
+    for name in dir.iter().skip(1) {
+        match globs.next() {
+            None => return false,
+            Some(glob) if glob.contains("**") => return true,
+            Some(glob) => {
+                let matched = Pattern::new(glob).map_or(true, |glob| {
+                    glob.matches_with(&name.to_string_lossy(), GLOB_OPTIONS)
+                });
+                if !matched {
+                    return false;
+                }
+            }
+        }
+    }
+    globs.next().is_some()
+}
+
+fn any_match(patterns: &[Pattern], path: &Path) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.as_str().contains('/') {
+            true => pattern.matches_path_with(path, GLOB_OPTIONS),
+            false => path
+                .file_name()
+                .is_some_and(|name| pattern.matches_with(&name.to_string_lossy(), GLOB_OPTIONS)),
+        })
+}
+
+/// The result of scanning one entry
+struct Scan {
+    scanned: Scanned,
+    xattrs:  BTreeMap<OsString, Vec<u8>>,
+}
+
+fn read_xattrs(path: &Path, exclude: &[String]) -> Result<BTreeMap<OsString, Vec<u8>>> {
+    let mut xattrs: BTreeMap<OsString, Vec<u8>> = BTreeMap::new();
+    for attr in xattr::list(path)? {
+        let name = attr.to_string_lossy();
+        if exclude
+            .iter()
+            .any(|prefix| name.starts_with(prefix.as_str()))
+        {
+            continue;
+        }
+        if let Some(value) = xattr::get(path, &attr)? {
+            xattrs.insert(attr, value);
+        }
+    }
+    Ok(xattrs)
+}
+
+/// Do the expensive part of adding an entry: hashing contents and reading links and xattrs
+fn scan(entry: &Entry, exclude_xattrs: &[String]) -> Result<Scan> {
+    let file_type = entry.meta.file_type();
+    let scanned = if file_type.is_file() {
+        Scanned::File(match entry.meta.len() {
+            0 => None,
+            size => Some(signing::file_digest(
+                &mut File::open(&entry.source)?,
+                0,
+                size,
+            )?),
+        })
+    } else if file_type.is_dir() {
+        Scanned::Directory
+    } else if file_type.is_symlink() {
+        Scanned::Symlink(fs::read_link(&entry.source)?.into_os_string())
+    } else if file_type.is_char_device() {
+        Scanned::Char
+    } else {
+        return Err(ParcelError::UnsupportedType(format!(
+            "{:?} ({})",
+            file_type,
+            entry.source.display()
+        ))
+        .into());
+    };
+    Ok(Scan {
+        scanned,
+        xattrs: read_xattrs(&entry.source, exclude_xattrs)?,
+    })
+}
+
+impl ParcelBuilder {
+    /// Create a builder using one worker per available CPU
+    pub fn new() -> Self {
+        Self {
+            threads:         thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            clamp_time:      None,
+            zero_owner:      false,
+            normalize_perms: false,
+            sorted:          false,
+            exclude_xattrs:  Vec::new(),
+            exclude:         Vec::new(),
+            include:         Vec::new(),
+            one_file_system: false,
+        }
+    }
+
+    /// Set the number of worker threads
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Clamp atimes, mtimes and ctimes to at most `time` (usually `SOURCE_DATE_EPOCH`)
+    pub fn clamp_time(mut self, time: SystemTime) -> Self {
+        self.clamp_time = Some(time);
+        self
+    }
+
+    /// Make everything owned by uid and gid 0
+    pub fn zero_owner(mut self, zero_owner: bool) -> Self {
+        self.zero_owner = zero_owner;
+        self
+    }
+
+    /// Replace permissions with 0755 for directories and executables, and 0644 otherwise
+    pub fn normalize_perms(mut self, normalize_perms: bool) -> Self {
+        self.normalize_perms = normalize_perms;
+        self
+    }
+
+    /// Walk directories in name order, so inode numbers follow the sorted paths rather than
+    /// the order the filesystem happens to list them in
+    pub fn sorted(mut self, sorted: bool) -> Self {
+        self.sorted = sorted;
+        self
+    }
+
+    /// Leave out xattrs whose names start with `prefix`
+    pub fn exclude_xattr(mut self, prefix: impl Into<String>) -> Self {
+        self.exclude_xattrs.push(prefix.into());
+        self
+    }
+
+    /// Leave out xattrs that vary between build hosts, such as SELinux labels
+    pub fn exclude_volatile_xattrs(mut self) -> Self {
+        self.exclude_xattrs
+            .extend(VOLATILE_XATTRS.iter().map(|x| x.to_string()));
+        self
+    }
+
+    /// Leave out paths matching a glob, and everything beneath them.
+    /// Globs containing `/` match the path beneath the input; others match file names.
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.exclude.push(glob.into());
+        self
+    }
+
+    /// Keep paths matching a glob even if they or a directory above them match an exclusion.
+    /// Excluded directories holding included paths are added, without their other contents.
+    pub fn include(mut self, glob: impl Into<String>) -> Self {
+        self.include.push(glob.into());
+        self
+    }
+
+    /// Don't descend into directories on other filesystems than the input
+    pub fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Enable every option needed for reproducible output, clamping times to `epoch`
+    pub fn reproducible(self, epoch: SystemTime) -> Self {
+        self.clamp_time(epoch)
+            .zero_owner(true)
+            .normalize_perms(true)
+            .sorted(true)
+            .exclude_volatile_xattrs()
+    }
+
+    /// Apply the configured normalizations to an entry's attributes
+    fn normalize(&self, attrs: &mut InodeAttr, kind: InodeKind) {
+        if let Some(clamp) = self.clamp_time {
+            attrs.atime = attrs.atime.min(clamp);
+            attrs.mtime = attrs.mtime.min(clamp);
+            attrs.ctime = attrs.ctime.min(clamp);
+        }
+        if self.zero_owner {
+            attrs.uid = 0;
+            attrs.gid = 0;
+        }
+        if self.normalize_perms {
+            let perm = match kind {
+                InodeKind::Symlink => 0o777,
+                InodeKind::Directory => 0o755,
+                _ if attrs.perm & 0o111 != 0 => 0o755,
+                _ => 0o644,
+            };
+            attrs.perm = (attrs.perm & !0o7777) | perm;
+        }
+    }
+
+    /// Add everything beneath `input` to the root of the parcel
+    pub fn add_dir(&self, parcel: &mut ParcelHandle, input: &Path) -> Result<()> {
+        self.add_dir_at(parcel, input, Path::new("/"))
+    }
+
+    /// Add everything beneath `input` to the directory `dest` within the parcel,
+    /// creating it if necessary
+    pub fn add_dir_at(&self, parcel: &mut ParcelHandle, input: &Path, dest: &Path) -> Result<()> {
+        let exclude = compile_globs(&self.exclude)?;
+        let include = compile_globs(&self.include)?;
+        let dest = Path::new("/").join(dest).lexiclean();
+
+        let mut dir_map: BTreeMap<PathBuf, u64> = BTreeMap::new();
+        dir_map.insert(dest.clone(), parcel.ensure_dir(&dest)?);
+
+        let mut walker = WalkDir::new(input)
+            .min_depth(1)
+            .same_file_system(self.one_file_system);
+        if self.sorted {
+            walker = walker.sort_by_file_name();
+        }
+        // Excluded directories are still walked if an include could match beneath them, and
+        // are held back until something inside them turns out to be kept
+        let mut walker = walker.into_iter();
+        let mut held: Vec<(DirEntry, bool)> = Vec::new();
+        let mut ready: VecDeque<DirEntry> = VecDeque::new();
+        let mut walker = iter::from_fn(|| loop {
+            if let Some(entry) = ready.pop_front() {
+                return Some(Ok(entry));
+            }
+            let entry = match walker.next()? {
+                Ok(entry) => entry,
+                Err(err) => return Some(Err(err)),
+            };
+            while held
+                .last()
+                .is_some_and(|(dir, _)| dir.depth() >= entry.depth())
+            {
+                held.pop();
+            }
+            let path = match entry.path().strip_prefix(input) {
+                Ok(rel) => Path::new("/").join(rel),
+                Err(_) => return Some(Ok(entry)),
+            };
+            if kept(&exclude, &include, &path) {
+                for (dir, added) in held.iter_mut().filter(|(_, added)| !*added) {
+                    ready.push_back(dir.clone());
+                    *added = true;
+                }
+                ready.push_back(entry);
+            } else if entry.file_type().is_dir() {
+                match include
+                    .iter()
+                    .any(|pattern| could_match_below(pattern, &path))
+                {
+                    true => held.push((entry, false)),
+                    false => walker.skip_current_dir(),
+                }
+            }
+        });
+        loop {
+            let batch = walker
+                .by_ref()
+                .take(self.threads * BATCH_PER_THREAD)
+                .map(|entry| {
+                    let entry = entry?;
+                    Ok(Entry {
+                        path:   dest.join(entry.path().strip_prefix(input)?),
+                        meta:   entry.metadata()?,
+                        source: entry.into_path(),
+                    })
+                })
+                .collect::<Result<Vec<Entry>>>()?;
+            if batch.is_empty() {
+                return Ok(());
+            }
+            for (entry, scan) in batch.iter().zip(self.scan_all(&batch)) {
+                let Scan { scanned, xattrs } = scan?;
+                let parent = *dir_map
+                    .get(entry.path.parent().unwrap_or(&dest))
+                    .ok_or(ParcelError::Enoent)?;
+                let name = entry.path.file_name().ok_or(ParcelError::Enoent)?;
+                let kind = scanned.kind();
+                let mut attrs = InodeAttr::from_meta(&entry.meta);
+                self.normalize(&mut attrs, kind);
+                let ino = match scanned {
+                    Scanned::File(Some(digest)) => parcel.add_file_digest(
+                        FileAdd::Name(entry.source.clone().into_os_string()),
+                        digest,
+                        attrs,
+                        xattrs,
+                    )?,
+                    Scanned::File(None) => parcel.add_file(FileAdd::Empty, attrs, xattrs)?,
+                    Scanned::Directory => {
+                        let ino = parcel.add_directory(attrs, xattrs)?;
+                        dir_map.insert(entry.path.clone(), ino);
+                        ino
+                    }
+                    Scanned::Symlink(target) => parcel.add_symlink(target, attrs, xattrs)?,
+                    Scanned::Char => parcel.add_char(attrs, xattrs)?,
+                };
+                parcel.insert_dirent(parent, name.to_os_string(), ino, kind)?;
+            }
+        }
+    }
+
+    /// Scan a batch of entries in parallel, returning the results in the batch's order
+    fn scan_all(&self, batch: &[Entry]) -> Vec<Result<Scan>> {
+        let next = AtomicUsize::new(0);
+        let (tx, rx) = mpsc::channel();
+        thread::scope(|s| {
+            for _ in 0..self.threads.min(batch.len()) {
+                let tx = tx.clone();
+                let next = &next;
+                s.spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    match batch.get(i) {
+                        Some(entry) => tx
+                            .send((i, scan(entry, &self.exclude_xattrs)))
+                            .expect("Scan results dropped while workers were running"),
+                        None => break,
+                    }
+                });
+            }
+        });
+        drop(tx);
+        let mut results: Vec<_> = rx.into_iter().collect();
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, res)| res).collect()
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    os::unix::{fs::FileExt, io::AsRawFd},
+};
+
+use anyhow::Result;
+
+/// Size of the buffer used when the kernel can't copy for us
+const BUFFER_SIZE: usize = 128 * 1024;
+
+/// Copy `len` bytes from `offset` in `source` to the current position of `dest`, within the
+/// kernel where possible. The position of `source` is left alone.
+pub fn copy_range<W: Write + AsRawFd>(
+    source: &File,
+    offset: u64,
+    len: u64,
+    dest: &mut W,
+) -> Result<()> {
+    // Anything buffered has to reach the descriptor before we write to it directly
+    dest.flush()?;
+    #[cfg(target_os = "linux")]
+    if kernel_copy(source, offset, len, dest)? {
+        return Ok(());
+    }
+    buffered_copy(source, offset, len, dest)
+}
+
+/// Copy with `copy_file_range`, or else `sendfile`. Returns false, having copied nothing, if
+/// neither works with these descriptors.
+#[cfg(target_os = "linux")]
+fn kernel_copy<W: AsRawFd>(source: &File, offset: u64, len: u64, dest: &W) -> Result<bool> {
+    let (fd_in, fd_out) = (source.as_raw_fd(), dest.as_raw_fd());
+    let mut off_in = offset as libc::loff_t;
+    let mut use_sendfile = false;
+    let mut copied = 0;
+    while copied < len {
+        let chunk = (len - copied).min(1 << 30) as usize;
+        let res = match use_sendfile {
+            false => unsafe {
+                libc::copy_file_range(fd_in, &mut off_in, fd_out, std::ptr::null_mut(), chunk, 0)
+            },
+            true => unsafe { libc::sendfile(fd_out, fd_in, &mut off_in, chunk) },
+        };
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                // Pipes, mismatched filesystems and old kernels: try the next method
+                Some(
+                    libc::EXDEV | libc::EINVAL | libc::ENOSYS | libc::EOPNOTSUPP | libc::EBADF,
+                ) if copied == 0 => {
+                    match use_sendfile {
+                        false => use_sendfile = true,
+                        true => return Ok(false),
+                    }
+                    continue;
+                }
+                _ => return Err(err.into()),
+            }
+        }
+        if res == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        copied += res as u64;
+    }
+    Ok(true)
+}
+
+/// Copy through a userspace buffer
+fn buffered_copy<W: Write>(source: &File, offset: u64, len: u64, dest: &mut W) -> Result<()> {
+    let mut buf = vec![0u8; BUFFER_SIZE.min(len as usize)];
+    let mut copied = 0;
+    while copied < len {
+        let chunk = (len - copied).min(buf.len() as u64) as usize;
+        source.read_exact_at(&mut buf[..chunk], offset + copied)?;
+        dest.write_all(&buf[..chunk])?;
+        copied += chunk as u64;
+    }
+    dest.flush()?;
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Result;
+
+use crate::{error::ParcelError, InodeKind, ParcelHandle};
+
+/// Magic for the "new ASCII" (newc) cpio format, as read by the kernel's initramfs unpacker
+const NEWC_MAGIC: &[u8] = b"070701";
+/// Name of the entry terminating a cpio archive
+const TRAILER: &str = "TRAILER!!!";
+
+/// An object visible in the merged view of several layers
+#[derive(Clone, Copy)]
+struct Visible {
+    layer: usize,
+    ino:   u64,
+    kind:  InodeKind,
+}
+
+/// Merge one layer's directory into the overlay view. Whiteouts hide lower entries, and
+/// non-directories hide any lower subtree at the same path.
+fn merge_dir(
+    handle: &ParcelHandle,
+    layer: usize,
+    dir: u64,
+    path: &Path,
+    merged: &mut BTreeMap<PathBuf, Visible>,
+) -> Result<()> {
+    for (ino, kind, name) in handle.readdir(dir).ok_or(ParcelError::NotDirectory)? {
+        let path = path.join(name);
+        let replaces_dir = matches!(merged.get(&path), Some(v) if v.kind == InodeKind::Directory);
+        if kind != InodeKind::Directory && replaces_dir {
+            let hidden: Vec<PathBuf> = merged
+                .range(path.clone()..)
+                .map(|(p, _)| p)
+                .take_while(|p| p.starts_with(&path))
+                .cloned()
+                .collect();
+            for p in hidden {
+                merged.remove(&p);
+            }
+        }
+        if kind == InodeKind::Whiteout {
+            merged.remove(&path);
+            continue;
+        }
+        merged.insert(path.clone(), Visible { layer, ino, kind });
+        if kind == InodeKind::Directory {
+            merge_dir(handle, layer, ino, &path, merged)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write one newc header and name, padded to a 4-byte boundary
+#[allow(clippy::too_many_arguments)]
+fn write_header<W: Write>(
+    writer: &mut W,
+    ino: u64,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u32,
+    mtime: u64,
+    filesize: u64,
+    rdev: u64,
+    name: &[u8],
+) -> Result<()> {
+    let fields = [
+        ino,
+        mode as u64,
+        uid as u64,
+        gid as u64,
+        nlink as u64,
+        mtime,
+        filesize,
+        0,
+        0,
+        libc::major(rdev) as u64,
+        libc::minor(rdev) as u64,
+        name.len() as u64 + 1,
+        0,
+    ];
+    writer.write_all(NEWC_MAGIC)?;
+    for field in fields {
+        write!(writer, "{:08X}", field as u32)?;
+    }
+    writer.write_all(name)?;
+    writer.write_all(&[0])?;
+    pad(writer, NEWC_MAGIC.len() + fields.len() * 8 + name.len() + 1)
+}
+
+fn pad<W: Write>(writer: &mut W, len: usize) -> Result<()> {
+    writer.write_all(&[0; 3][..(4 - len % 4) % 4])?;
+    Ok(())
+}
+
+impl ParcelHandle {
+    /// Write the subtree rooted at `path` as a newc cpio archive
+    pub fn export_cpio<W: Write>(&mut self, path: PathBuf, writer: W) -> Result<()> {
+        Self::export_cpio_overlay(std::slice::from_mut(self), path, writer)
+    }
+
+    /// Write several parcels as one newc cpio archive, later layers overriding earlier ones.
+    /// Layers that lack `path` are skipped.
+    pub fn export_cpio_overlay<W: Write>(
+        layers: &mut [ParcelHandle],
+        path: PathBuf,
+        mut writer: W,
+    ) -> Result<()> {
+        let mut merged: BTreeMap<PathBuf, Visible> = BTreeMap::new();
+        for (layer, handle) in layers.iter().enumerate() {
+            if let Some(top) = handle.select(path.clone()) {
+                merge_dir(handle, layer, top, Path::new(""), &mut merged)?;
+            }
+        }
+
+        let mut links: BTreeMap<(usize, u64), u32> = BTreeMap::new();
+        for v in merged.values() {
+            *links.entry((v.layer, v.ino)).or_default() += 1;
+        }
+        let numbers: BTreeMap<(usize, u64), u64> = links
+            .keys()
+            .enumerate()
+            .map(|(i, k)| (*k, i as u64 + 1))
+            .collect();
+
+        let mut written: BTreeSet<(usize, u64)> = BTreeSet::new();
+        for (path, v) in merged.iter() {
+            let handle = &mut layers[v.layer];
+            let attr = handle.getattr(v.ino).ok_or(ParcelError::Enoent)?;
+            let type_bits = match v.kind {
+                InodeKind::Directory => libc::S_IFDIR,
+                InodeKind::RegularFile => libc::S_IFREG,
+                InodeKind::Symlink => libc::S_IFLNK,
+                InodeKind::CharDevice => libc::S_IFCHR,
+                InodeKind::Whiteout => continue,
+            };
+            // Hard-linked data goes with the first name; the kernel links later names to it
+            let data = match v.kind {
+                InodeKind::RegularFile if written.insert((v.layer, v.ino)) => {
+                    handle.read(v.ino, 0, None)?
+                }
+                InodeKind::Symlink => handle.readlink(v.ino).ok_or(ParcelError::Enoent)?,
+                _ => Vec::new(),
+            };
+            let rdev = match v.kind {
+                InodeKind::CharDevice => attr.rdev as u64,
+                _ => 0,
+            };
+            write_header(
+                &mut writer,
+                numbers[&(v.layer, v.ino)],
+                type_bits | (attr.perm as u32 & 0o7777),
+                attr.uid,
+                attr.gid,
+                links[&(v.layer, v.ino)],
+                attr.mtime
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                data.len() as u64,
+                rdev,
+                path.as_os_str().as_bytes(),
+            )?;
+            writer.write_all(&data)?;
+            pad(&mut writer, data.len())?;
+        }
+
+        write_header(&mut writer, 0, 0, 0, 0, 1, 0, 0, 0, TRAILER.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+}
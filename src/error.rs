@@ -12,6 +12,9 @@ pub enum ParcelError {
     /// Trying to read from an object that's not a file
     #[error("Requested object not a file")]
     NotFile,
+    /// Trying to list an object that's not a directory
+    #[error("Requested object not a directory")]
+    NotDirectory,
     /// Reading a parcel without a version field
     #[error("Missing version field")]
     NoVersion,
@@ -22,7 +25,51 @@ pub enum ParcelError {
     #[error("Version Mismatch (expected {expected:?}, got {found:?})")]
     #[allow(missing_docs)]
     VersionMismatch { expected: u32, found: u32 },
+    /// The header's index or records are malformed
+    #[error("Corrupt parcel header")]
+    CorruptHeader,
     /// Writing past the end of a file
     #[error("Need to expand file before writing")]
     NeedExpansion,
+    /// Verifying a parcel that carries no signature
+    #[error("Parcel is not signed")]
+    Unsigned,
+    /// The parcel's signature does not match its header or the given key
+    #[error("Bad parcel signature")]
+    BadSignature,
+    /// A file's contents do not match the digest recorded in the signed header
+    #[error("Digest mismatch for inode {ino}")]
+    #[allow(missing_docs)]
+    DigestMismatch { ino: u64 },
+    /// Importing an object of a type parcels cannot represent
+    #[error("Unsupported file type: {0}")]
+    UnsupportedType(String),
+    /// A manifest line could not be parsed
+    #[error("Manifest line {line}: {reason}")]
+    #[allow(missing_docs)]
+    ManifestSyntax { line: usize, reason: String },
+    /// A manifest entry conflicts with the parcel, or describes an object it cannot create
+    #[error("Cannot apply manifest entry for {0}")]
+    ManifestPath(String),
+    /// Another process holds a conflicting lock on the parcel
+    #[error("Parcel is locked by another process")]
+    Locked,
+    /// Reading file data that only exists in memory until the parcel is stored
+    #[error("Parcel has changes that have not been stored")]
+    NotStored,
+    /// An operation that needs the parcel to be backed by a file on disk
+    #[error("Parcel is not backed by a file")]
+    NotFileBacked,
+    /// A web server that doesn't report sizes or honour range requests
+    #[error("Server does not support range requests")]
+    RangesUnsupported,
+    /// A directory entry whose name isn't a single path component, such as `..` or `a/b`
+    #[error("Unsafe directory entry name: {0:?}")]
+    BadName(String),
+    /// Loading a parcel whose last store was interrupted from a backing that can't be written
+    #[error("Parcel has an interrupted store to finish; load it writable to recover")]
+    NeedsRecovery,
+    /// Storing a parcel while a transaction on it is still open
+    #[error("Cannot store while a transaction is open")]
+    TransactionOpen,
 }
@@ -0,0 +1,196 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{CString, OsString},
+    fs::{self, File},
+    io::{self, Seek, SeekFrom},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::{symlink, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{error::ParcelError, signing, InodeKind, ParcelHandle};
+
+/// What to do when a config file already exists at the extraction destination
+#[derive(Debug, Clone)]
+pub enum ConfigPolicy {
+    /// Leave the installed file alone and drop the new contents
+    KeepExisting,
+    /// Leave the installed file alone and write the new contents beside it as `<name>.new`
+    WriteNew,
+    /// Overwrite the installed file if its digest matches the one shipped by the previous
+    /// package (from [`ParcelHandle::config_digests`]), otherwise write `<name>.new`
+    ReplaceUnmodified(BTreeMap<String, String>),
+}
+
+/// Append `.new` to a file name
+fn new_path(dest: &Path) -> PathBuf {
+    let mut name = dest.as_os_str().to_os_string();
+    name.push(".new");
+    PathBuf::from(name)
+}
+
+/// Remove whatever is at `dest` so it can be replaced, unless it is a directory. A symlink is
+/// removed rather than followed, so one left at `dest` can't redirect what is written there.
+fn clear(dest: &Path) -> Result<()> {
+    match fs::symlink_metadata(dest) {
+        Ok(meta) if !meta.is_dir() => fs::remove_file(dest)?,
+        _ => (),
+    }
+    Ok(())
+}
+
+fn cstring(path: &Path) -> Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+impl ParcelHandle {
+    /// Extract the parcel's contents beneath `root`
+    pub fn extract(&mut self, root: &Path, policy: &ConfigPolicy) -> Result<()> {
+        let mut extracted: BTreeMap<u64, PathBuf> = BTreeMap::new();
+        let mut dirs: Vec<(PathBuf, u32)> = Vec::new();
+        let mut queue = vec![(1, PathBuf::from("/"))];
+        while let Some((dir, path)) = queue.pop() {
+            for (ino, kind, name) in self.readdir(dir).ok_or(ParcelError::Enoent)? {
+                if kind == InodeKind::Whiteout {
+                    continue;
+                }
+                // Each name must stay within its directory, so nothing is written outside `root`
+                if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']) {
+                    return Err(ParcelError::BadName(name).into());
+                }
+                let path = path.join(&name);
+                let dest = root.join(path.strip_prefix("/")?);
+                if let Some(first) = extracted.get(&ino) {
+                    clear(&dest)?;
+                    fs::hard_link(first, &dest)?;
+                    continue;
+                }
+                if kind == InodeKind::Directory {
+                    queue.push((ino, path.clone()));
+                    let perm = self.getattr(ino).ok_or(ParcelError::Enoent)?.perm as u32;
+                    dirs.push((dest.clone(), perm));
+                }
+                if let Some(dest) = self.extract_inode(ino, kind, &path, &dest, policy)? {
+                    extracted.insert(ino, dest);
+                }
+            }
+        }
+        // Directory permissions are applied last, so read-only directories can still be filled
+        for (dest, perm) in dirs.iter().rev() {
+            fs::set_permissions(dest, fs::Permissions::from_mode(perm & 0o7777))?;
+        }
+        Ok(())
+    }
+
+    /// Create a single inode at `dest`, returning where it was written (if anywhere)
+    fn extract_inode(
+        &mut self,
+        ino: u64,
+        kind: InodeKind,
+        path: &Path,
+        dest: &Path,
+        policy: &ConfigPolicy,
+    ) -> Result<Option<PathBuf>> {
+        let attr = self.getattr(ino).ok_or(ParcelError::Enoent)?;
+        let dest = match kind {
+            InodeKind::Directory => {
+                if !fs::symlink_metadata(dest).is_ok_and(|meta| meta.is_dir()) {
+                    clear(dest)?;
+                    fs::create_dir_all(dest)?;
+                }
+                dest.to_path_buf()
+            }
+            InodeKind::RegularFile => {
+                let dest = match self.config_destination(ino, path, dest, policy)? {
+                    Some(dest) => dest,
+                    None => return Ok(None),
+                };
+                // Only ever write to a file created here, never through what was there before
+                clear(&dest)?;
+                let mut file = File::options().write(true).create_new(true).open(&dest)?;
+                match self.segments(ino) {
+                    // Only write the data, so the holes are recreated
+                    Some(segments) => {
+                        file.set_len(attr.size)?;
+                        for (start, len) in segments {
+                            file.seek(SeekFrom::Start(start))?;
+                            self.copy_to(ino, start, Some(len), &mut file)?;
+                        }
+                    }
+                    None => {
+                        self.copy_to(ino, 0, None, &mut file)?;
+                    }
+                }
+                dest
+            }
+            InodeKind::Symlink => {
+                let target = self.readlink(ino).ok_or(ParcelError::Enoent)?;
+                clear(dest)?;
+                symlink(OsString::from_vec(target), dest)?;
+                dest.to_path_buf()
+            }
+            InodeKind::CharDevice => {
+                clear(dest)?;
+                let res = unsafe {
+                    libc::mknod(
+                        cstring(dest)?.as_ptr(),
+                        libc::S_IFCHR | (attr.perm as u32 & 0o7777),
+                        attr.rdev as libc::dev_t,
+                    )
+                };
+                if res != 0 {
+                    return Err(io::Error::last_os_error().into());
+                }
+                dest.to_path_buf()
+            }
+            InodeKind::Whiteout => return Ok(None),
+        };
+
+        if kind != InodeKind::Symlink && kind != InodeKind::Directory {
+            fs::set_permissions(&dest, fs::Permissions::from_mode(attr.perm as u32 & 0o7777))?;
+        }
+        if unsafe { libc::geteuid() } == 0 {
+            let res = unsafe { libc::lchown(cstring(&dest)?.as_ptr(), attr.uid, attr.gid) };
+            if res != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        for (name, value) in self.getxattrs(ino).unwrap_or_default() {
+            xattr::set(&dest, name, &value)?;
+        }
+        Ok(Some(dest))
+    }
+
+    /// Decide where a regular file should be written, honouring the config file policy
+    fn config_destination(
+        &mut self,
+        ino: u64,
+        path: &Path,
+        dest: &Path,
+        policy: &ConfigPolicy,
+    ) -> Result<Option<PathBuf>> {
+        // Only a regular file counts as installed; anything else is replaced
+        let installed = fs::symlink_metadata(dest).is_ok_and(|meta| meta.is_file());
+        if !self.is_config(path.to_path_buf()) || !installed {
+            return Ok(Some(dest.to_path_buf()));
+        }
+        let installed = signing::file_digest(&mut File::open(dest)?, 0, fs::metadata(dest)?.len())?;
+        if installed == self.digest(ino)? {
+            return Ok(Some(dest.to_path_buf()));
+        }
+        Ok(match policy {
+            ConfigPolicy::KeepExisting => None,
+            ConfigPolicy::WriteNew => Some(new_path(dest)),
+            ConfigPolicy::ReplaceUnmodified(previous) => {
+                match path.to_str().and_then(|p| previous.get(p)) {
+                    Some(digest) if *digest == installed => Some(dest.to_path_buf()),
+                    _ => Some(new_path(dest)),
+                }
+            }
+        })
+    }
+}
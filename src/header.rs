@@ -0,0 +1,280 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ParcelError,
+    inode::{Inode, InodeContent},
+    metadata::ParcelMetadata,
+};
+
+/// Marks the start of the inode index within the header (always at the start of a line)
+const INDEX_KEY: &[u8] = b"index: |\n";
+/// Marks the start of the inode records within the header
+const RECORDS_KEY: &[u8] = b"records:\n";
+/// Indentation of index lines and record bodies
+const INDENT: &[u8] = b"    ";
+/// Prefix of the first line of each record, making the records a YAML sequence
+const ITEM: &[u8] = b"  - ";
+/// Length of an index line: `  <ino:16> <offset:16> <len:8>\n`
+const INDEX_LINE: usize = 2 + 16 + 1 + 16 + 1 + 8 + 1;
+
+/// Where the data section and header are, as recorded on the line after the magic. The header
+/// lives in a slot that can be moved past the end of the data section when it outgrows its
+/// space, so file data never has to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// Position of the data section
+    pub data:     u64,
+    /// Position of the header
+    pub header:   u64,
+    /// Length of the header
+    pub len:      u64,
+    /// Space reserved for the header, which can be rewritten in place while it fits
+    pub capacity: u64,
+}
+
+impl Layout {
+    /// Length of the layout line: `@<data:16> <header:16> <len:16> <capacity:16>\n`
+    pub const LEN: u64 = 1 + 4 * 17;
+
+    /// Render the layout line
+    pub fn render(&self) -> Vec<u8> {
+        format!(
+            "@{:016x} {:016x} {:016x} {:016x}\n",
+            self.data, self.header, self.len, self.capacity
+        )
+        .into_bytes()
+    }
+
+    /// Parse a layout line
+    pub fn parse(line: &[u8]) -> Result<Self> {
+        let fields = std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| line.strip_prefix('@'))
+            .and_then(|line| line.strip_suffix('\n'))
+            .map(|line| {
+                line.split(' ')
+                    .map(|field| u64::from_str_radix(field, 16).ok())
+                    .collect::<Option<Vec<u64>>>()
+            });
+        match fields {
+            Some(Some(fields)) if fields.len() == 4 => Ok(Self {
+                data:     fields[0],
+                header:   fields[1],
+                len:      fields[2],
+                capacity: fields[3],
+            }),
+            _ => Err(ParcelError::CorruptHeader.into()),
+        }
+    }
+}
+
+/// The part of the header that is always decoded on load
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Summary<'a> {
+    pub version:    u32,
+    pub root_inode: u64,
+    pub metadata:   Cow<'a, ParcelMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature:  Option<Cow<'a, str>>,
+    /// Boundary the data section and each file's data start on, if aligned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alignment:  Option<u64>,
+}
+
+/// One inode and its contents, as stored in the header
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record<'a> {
+    pub ino:     u64,
+    pub inode:   Cow<'a, Inode>,
+    pub content: Cow<'a, InodeContent>,
+}
+
+/// Serialize a value as the body of a YAML document, without the leading `---`
+fn yaml_body<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let buf = serde_yaml::to_vec(value)?;
+    Ok(match buf.strip_prefix(b"---\n") {
+        Some(body) => body.to_vec(),
+        None => buf,
+    })
+}
+
+/// Render the summary part of the header
+pub fn render_summary(summary: &Summary) -> Result<Vec<u8>> {
+    let mut buf = serde_yaml::to_vec(summary)?;
+    if !buf.ends_with(b"\n") {
+        buf.push(b'\n');
+    }
+    Ok(buf)
+}
+
+/// Render the records of every inode, returning the index and record blocks.
+/// Record offsets in the index are relative to the start of the record block.
+pub fn render_records(
+    inodes: &BTreeMap<u64, Inode>,
+    content: &BTreeMap<u64, InodeContent>,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut index = Vec::with_capacity(inodes.len() * INDEX_LINE);
+    let mut records = Vec::new();
+    for (ino, inode) in inodes.iter() {
+        let record = Record {
+            ino:     *ino,
+            inode:   Cow::Borrowed(inode),
+            content: Cow::Borrowed(
+                content
+                    .get(ino)
+                    .expect("Inode has no content, parcel is inconsistent"),
+            ),
+        };
+        let start = records.len();
+        for (i, line) in yaml_body(&record)?
+            .split_inclusive(|c| *c == b'\n')
+            .enumerate()
+        {
+            records.extend_from_slice(if i == 0 { ITEM } else { INDENT });
+            records.extend_from_slice(line);
+        }
+        index.extend_from_slice(
+            format!(
+                "  {:016x} {:016x} {:08x}\n",
+                ino,
+                start,
+                records.len() - start
+            )
+            .as_bytes(),
+        );
+    }
+    Ok((index, records))
+}
+
+/// Assemble a complete header from its rendered parts
+pub fn assemble(summary: &[u8], index: &[u8], records: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(summary.len() + index.len() + records.len() + 32);
+    buf.extend_from_slice(summary);
+    buf.extend_from_slice(INDEX_KEY);
+    buf.extend_from_slice(index);
+    buf.extend_from_slice(RECORDS_KEY);
+    buf.extend_from_slice(records);
+    buf
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Get the summary part of a header, which is the whole header for older versions
+pub fn summary_bytes(buf: &[u8]) -> &[u8] {
+    let mut needle = b"\n".to_vec();
+    needle.extend_from_slice(INDEX_KEY);
+    match find(buf, &needle) {
+        Some(pos) => &buf[..pos + 1],
+        None => buf,
+    }
+}
+
+/// A loaded header whose records are decoded on demand
+#[derive(Debug, Clone)]
+pub struct LazyHeader {
+    buf:     Vec<u8>,
+    /// The inode, start and length within `buf` of each record, in ascending inode order
+    entries: Vec<(u64, usize, usize)>,
+}
+
+impl LazyHeader {
+    /// Locate the records within a header through its index. The index must list inodes in
+    /// ascending order and lead to each record in turn, covering the whole record block.
+    pub fn new(buf: Vec<u8>) -> Result<Self> {
+        let index = summary_bytes(&buf).len() + INDEX_KEY.len();
+        if index > buf.len() {
+            return Err(ParcelError::CorruptHeader.into());
+        }
+        let mut records = index;
+        while buf.get(records..).is_some_and(|b| b.starts_with(b"  ")) {
+            records += INDEX_LINE;
+        }
+        if !buf
+            .get(records..)
+            .unwrap_or_default()
+            .starts_with(RECORDS_KEY)
+        {
+            return Err(ParcelError::CorruptHeader.into());
+        }
+
+        let block = records + RECORDS_KEY.len();
+        let mut end = buf.len();
+        while end > block && buf[end - 1] == b' ' {
+            end -= 1;
+        }
+        let mut entries: Vec<(u64, usize, usize)> =
+            Vec::with_capacity((records - index) / INDEX_LINE);
+        let mut expected = block;
+        for line in buf[index..records].chunks(INDEX_LINE) {
+            let (ino, offset, len) = match (
+                hex(line.get(2..18)),
+                hex(line.get(19..35)),
+                hex(line.get(36..44)),
+            ) {
+                (Some(ino), Some(offset), Some(len)) => (ino, offset as usize, len as usize),
+                _ => return Err(ParcelError::CorruptHeader.into()),
+            };
+            let start = block.checked_add(offset);
+            let first = [ITEM, format!("ino: {}\n", ino).as_bytes()].concat();
+            let ordered = entries.last().is_none_or(|(last, ..)| *last < ino);
+            let fits = start == Some(expected)
+                && start
+                    .and_then(|s| s.checked_add(len))
+                    .is_some_and(|e| e <= end)
+                && buf[expected..expected + len].starts_with(&first);
+            if !ordered || !fits {
+                return Err(ParcelError::CorruptHeader.into());
+            }
+            entries.push((ino, expected, len));
+            expected += len;
+        }
+        if expected != end {
+            return Err(ParcelError::CorruptHeader.into());
+        }
+        Ok(Self { buf, entries })
+    }
+
+    /// Inode numbers in the header, in ascending order
+    pub fn inos(&self) -> impl Iterator<Item = u64> + '_ {
+        self.entries.iter().map(|(ino, ..)| *ino)
+    }
+
+    /// Check whether the header holds an inode
+    pub fn contains(&self, ino: u64) -> bool {
+        self.position(ino).is_some()
+    }
+
+    fn position(&self, ino: u64) -> Option<usize> {
+        self.entries
+            .binary_search_by_key(&ino, |(ino, ..)| *ino)
+            .ok()
+    }
+
+    /// Decode the record of a single inode, if the header holds it
+    pub fn record(&self, ino: u64) -> Result<Option<Record<'static>>> {
+        let (_, start, len) = match self.position(ino) {
+            Some(n) => self.entries[n],
+            None => return Ok(None),
+        };
+        let mut body = Vec::with_capacity(len);
+        for line in self.buf[start..start + len].split_inclusive(|c| *c == b'\n') {
+            body.extend_from_slice(line.get(INDENT.len()..).unwrap_or_default());
+        }
+        let record: Record = serde_yaml::from_slice(&body).or(Err(ParcelError::CorruptHeader))?;
+        if record.ino != ino {
+            return Err(ParcelError::CorruptHeader.into());
+        }
+        Ok(Some(record))
+    }
+}
+
+/// Parse a fixed-width hex field of an index line
+fn hex(field: Option<&[u8]>) -> Option<u64> {
+    let field = std::str::from_utf8(field?).ok()?;
+    u64::from_str_radix(field, 16).ok()
+}
@@ -0,0 +1,63 @@
+use std::io::{self, Read};
+
+use anyhow::Result;
+
+use crate::{error::ParcelError, random_access::RandomAccess};
+
+/// A file on a web server, read with HTTP range requests so that only the parts of a parcel
+/// that are used get fetched
+#[derive(Debug)]
+pub struct HttpRange {
+    agent: ureq::Agent,
+    url:   String,
+    size:  u64,
+}
+
+impl HttpRange {
+    /// Open the file at `url`, finding its size with a HEAD request
+    pub fn new(url: &str) -> Result<Self> {
+        let agent = ureq::Agent::new();
+        let response = agent.head(url).call()?;
+        let size = response
+            .header("Content-Length")
+            .and_then(|len| len.parse().ok())
+            .ok_or(ParcelError::RangesUnsupported)?;
+        Ok(Self {
+            agent,
+            url: url.into(),
+            size,
+        })
+    }
+}
+
+impl RandomAccess for HttpRange {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.size)
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let len = (buf.len() as u64).min(self.size.saturating_sub(offset));
+        if len == 0 {
+            return Ok(0);
+        }
+        let response = self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={}-{}", offset, offset + len - 1))
+            .call()
+            .map_err(io::Error::other)?;
+        // A server that ignores the range sends the whole file instead
+        if response.status() != 206 {
+            return Err(io::Error::other(ParcelError::RangesUnsupported));
+        }
+        let mut reader = response.into_reader().take(len);
+        let mut read = 0;
+        while read < len as usize {
+            match reader.read(&mut buf[read..len as usize])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        Ok(read)
+    }
+}
@@ -26,6 +26,8 @@ pub struct FileAttr {
 
 use serde::{Deserialize, Serialize};
 
+use crate::sparse::Segment;
+
 /// Contains the attributes of the inode
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub struct InodeAttr {
@@ -37,7 +39,8 @@ pub struct InodeAttr {
     pub ctime: SystemTime,
     /// Permissions
     pub perm:  u32,
-    /// Number of hard links
+    /// Number of hard links. Parcels count entries naming the inode beyond its first as they
+    /// are inserted, and uncount entries that are replaced.
     pub nlink: u32,
     /// Owner user ID
     pub uid:   u32,
@@ -115,6 +118,13 @@ pub struct FileReference {
     pub size:     u64,
     /// The amount of space reserved for the file
     pub capacity: u64,
+    /// Hex-encoded SHA-256 of the file's contents, recorded when the parcel is signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest:   Option<String>,
+    /// For files with holes, the (offset, length) of each run of data. Only these runs are
+    /// stored, back to back, and `capacity` counts just their bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segments: Option<Vec<Segment>>,
 }
 
 /// Describes the contents of the object
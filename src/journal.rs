@@ -0,0 +1,117 @@
+use std::{
+    cmp::max,
+    io::{Read, SeekFrom},
+};
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+use crate::{error::ParcelError, parcel::FileBacking};
+
+/// Marks the end of a journal, which is always the last thing in the backing file
+const MAGIC: &[u8; 8] = b"413JRNL\n";
+/// Length of the trailer: digest, start of the journal, magic
+const TRAILER: u64 = 32 + 8 + 8;
+
+/// Bytes to be written at a position in the backing file
+pub type Patch = (u64, Vec<u8>);
+
+/// Write `patches` so that an interrupted store leaves either none or all of them applied once
+/// the parcel is next loaded. They are first appended to the end of the backing file as a
+/// journal, then written in place, then the journal is dropped.
+pub fn commit(output: &mut dyn FileBacking, patches: &[Patch]) -> Result<()> {
+    let start = patches
+        .iter()
+        .map(|(pos, data)| pos + data.len() as u64)
+        .fold(output.seek(SeekFrom::End(0))?, max);
+    let mut journal = Vec::new();
+    for (pos, data) in patches {
+        journal.extend_from_slice(&pos.to_le_bytes());
+        journal.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        journal.extend_from_slice(data);
+    }
+    journal.extend_from_slice(&trailer_digest(&journal, start));
+    journal.extend_from_slice(&start.to_le_bytes());
+    journal.extend_from_slice(MAGIC);
+    output.seek(SeekFrom::Start(start))?;
+    output.write_all(&journal)?;
+    output.sync()?;
+
+    apply(output, patches)?;
+    output.sync()?;
+    discard(output, start)
+}
+
+/// Finish a store that was interrupted after its journal was written. Fails with
+/// [`ParcelError::NeedsRecovery`] if there is one but the backing can't be written, as the
+/// parcel may be half stored.
+pub fn recover(output: &mut dyn FileBacking) -> Result<()> {
+    let end = output.seek(SeekFrom::End(0))?;
+    if end < TRAILER {
+        return Ok(());
+    }
+    let mut trailer = [0u8; TRAILER as usize];
+    output.seek(SeekFrom::Start(end - TRAILER))?;
+    output.read_exact(&mut trailer)?;
+    if &trailer[40..] != MAGIC {
+        return Ok(());
+    }
+    let start = u64::from_le_bytes(trailer[32..40].try_into()?);
+    let mut journal = Vec::new();
+    if start <= end - TRAILER {
+        output.seek(SeekFrom::Start(start))?;
+        Read::take(&mut *output, end - TRAILER - start).read_to_end(&mut journal)?;
+    }
+    // A journal torn while it was written never got as far as touching the old state. The
+    // magic alone could also just be the end of the last file's data, so nothing is changed.
+    if start > end - TRAILER || trailer_digest(&journal, start) != trailer[..32] {
+        return Ok(());
+    }
+    if !output.writable()? {
+        return Err(ParcelError::NeedsRecovery.into());
+    }
+    apply(output, &parse(&journal)?)?;
+    output.sync()?;
+    discard(output, start)
+}
+
+fn trailer_digest(journal: &[u8], start: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(journal);
+    hasher.update(start.to_le_bytes());
+    hasher.finalize().into()
+}
+
+fn parse(mut journal: &[u8]) -> Result<Vec<Patch>> {
+    let mut patches = Vec::new();
+    while !journal.is_empty() {
+        let mut word = [0u8; 8];
+        journal.read_exact(&mut word)?;
+        let pos = u64::from_le_bytes(word);
+        journal.read_exact(&mut word)?;
+        let mut data = vec![0u8; u64::from_le_bytes(word) as usize];
+        journal.read_exact(&mut data)?;
+        patches.push((pos, data));
+    }
+    Ok(patches)
+}
+
+fn apply(output: &mut dyn FileBacking, patches: &[Patch]) -> Result<()> {
+    for (pos, data) in patches {
+        output.seek(SeekFrom::Start(*pos))?;
+        output.write_all(data)?;
+    }
+    Ok(())
+}
+
+/// Drop an applied journal, cutting it off the end of the backing file if possible
+fn discard(output: &mut dyn FileBacking, start: u64) -> Result<()> {
+    // If truncating fails the journal is left whole, magic and all, to be replayed on next load
+    let truncated = output.set_len(start)?;
+    if !truncated {
+        output.seek(SeekFrom::End(-(MAGIC.len() as i64)))?;
+        output.write_all(&[0; MAGIC.len()])?;
+    }
+    output.sync()?;
+    Ok(())
+}
@@ -6,27 +6,85 @@
 #![allow(clippy::new_without_default)]
 
 //! Parcel file format for managing pyxis packages.
+//!
+//! With the `tokio` feature, `AsyncParcel` serves files from parcels to async code. With the
+//! `http` feature, `HttpRange` reads parcels from web servers through [`RandomAccessBacking`].
 
 use std::time::UNIX_EPOCH;
 
+#[cfg(feature = "tokio")]
+pub use async_parcel::{AsyncFile, AsyncParcel};
+pub use builder::ParcelBuilder;
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
 pub use error::ParcelError;
+pub use extract::ConfigPolicy;
+#[cfg(feature = "http")]
+pub use http::HttpRange;
 pub use inode::{FileAttr, InodeAttr, InodeKind};
-pub use parcel::{FileAdd, ParcelHandle};
+pub use manifest::{Manifest, ManifestEntry};
+pub use mtree::Drift;
+pub use parcel::{DedupSavings, FileAdd, FileBacking, ParcelHandle, ParcelView};
+pub use random_access::{RandomAccess, RandomAccessBacking};
+pub use spec::{validate, Violation};
+pub use transaction::Transaction;
 
+/// Reading parcels from async code
+#[cfg(feature = "tokio")]
+mod async_parcel;
+/// Building parcels from directory trees
+mod builder;
+/// Copying file data within the kernel
+mod copy;
+/// Export to newc cpio archives
+mod cpio;
 /// Error codes
 mod error;
+/// Unpacking a parcel onto the filesystem
+mod extract;
+/// Header layout with lazily decoded inode records
+mod header;
+/// Reading parcels from web servers with range requests
+#[cfg(feature = "http")]
+mod http;
 /// Inodes and utilities for representing items within a parcel.
 mod inode;
+/// Crash-safe stores through a redo journal
+mod journal;
+/// Advisory locking of backing files
+mod lock;
+/// Ownership and permission override manifests
+mod manifest;
 /// Parcel metadata for the package manager
 mod metadata;
+/// Loading parcels written by earlier format versions
+mod migrate;
+/// BSD mtree manifests and checks of installed roots
+mod mtree;
 /// The parcel container. Classes and methods.
 mod parcel;
+/// Positional reads of a file shared between threads
+mod positional;
+/// Loading parcels from read-only random-access sources
+mod random_access;
+/// Header signing and per-file digests
+mod signing;
+/// Files with holes
+mod sparse;
+/// The format specification, checked against stored parcels
+mod spec;
+/// Conversion between parcels and tar archives
+mod tarball;
+/// Transactions grouping changes to a parcel
+mod transaction;
 
 mod reader_writer;
 
 pub use reader_writer::ReaderWriter;
 
-const PARCEL_VERSION: u32 = 2;
+const PARCEL_VERSION: u32 = 4;
+
+/// Filesystem block size, for aligning file data with [`ParcelHandle::set_alignment`]
+pub const BLOCK_SIZE: u64 = 4096;
 
 const ROOT_ATTRS: InodeAttr = InodeAttr {
     atime: UNIX_EPOCH,
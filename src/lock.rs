@@ -0,0 +1,41 @@
+use std::{fs::File, io, os::unix::io::AsRawFd};
+
+/// Take an advisory lock on the whole of `file`: shared if it was opened read-only, exclusive
+/// otherwise. Returns false if `wait` is false and another process holds a conflicting lock.
+///
+/// These are POSIX record locks, which belong to the process: they never conflict with other
+/// handles in the same process, and are released when the process closes any descriptor for
+/// the file.
+pub fn lock(file: &File, wait: bool) -> io::Result<bool> {
+    let fd = file.as_raw_fd();
+    let mut request: libc::flock = unsafe { std::mem::zeroed() };
+    request.l_type = match writable(file)? {
+        false => libc::F_RDLCK,
+        true => libc::F_WRLCK,
+    } as libc::c_short;
+    request.l_whence = libc::SEEK_SET as libc::c_short;
+    let command = match wait {
+        true => libc::F_SETLKW,
+        false => libc::F_SETLK,
+    };
+    loop {
+        if unsafe { libc::fcntl(fd, command, &request) } == 0 {
+            return Ok(true);
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINTR) => continue,
+            Some(libc::EACCES | libc::EAGAIN) if !wait => return Ok(false),
+            _ => return Err(err),
+        }
+    }
+}
+
+/// Whether `file` was opened for writing
+pub fn writable(file: &File) -> io::Result<bool> {
+    let flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(flags & libc::O_ACCMODE != libc::O_RDONLY)
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{error::ParcelError, InodeAttr, InodeKind, ParcelHandle};
+
+/// Overrides for one path, as given by a manifest line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Absolute path within the parcel
+    pub path: PathBuf,
+    /// Type of object; required to create objects that aren't in the parcel
+    pub kind: Option<InodeKind>,
+    /// Owner user ID
+    pub uid:  Option<u32>,
+    /// Owner group ID
+    pub gid:  Option<u32>,
+    /// Permission bits, including setuid/setgid/sticky
+    pub mode: Option<u32>,
+    /// Device ID, for character devices
+    pub rdev: Option<u64>,
+    /// Target, for symlinks
+    pub link: Option<OsString>,
+}
+
+/// A list of per-path overrides in an mtree-like syntax, one path per line followed by
+/// `keyword=value` pairs:
+///
+/// ```text
+/// # comment
+/// /usr/bin/sudo uid=0 gid=0 mode=4755
+/// /dev/null type=char mode=0666 device=1,3
+/// ./run type=dir mode=0755
+/// ```
+///
+/// Supported keywords are `type` (`file`, `dir`, `link` or `char`), `uid`, `gid`, `mode`
+/// (octal), `device` (`major,minor`) and `link`; `size` and `sha256digest` are ignored, so
+/// `parcel-mtree` output can be used as a manifest. Paths may escape characters as `\ooo` octal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// Entries in the order they appear
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Undo mtree-style `\ooo` octal escapes
+pub(crate) fn unescape(s: &str) -> Option<OsString> {
+    let bytes = s.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            let digits = std::str::from_utf8(bytes.get(i + 1..i + 4)?).ok()?;
+            res.push(u8::from_str_radix(digits, 8).ok()?);
+            i += 4;
+        } else {
+            res.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(OsString::from_vec(res))
+}
+
+/// Escape whitespace, backslashes, `#` and non-printable bytes as mtree-style `\ooo`
+pub(crate) fn escape(s: &[u8]) -> String {
+    let mut res = String::with_capacity(s.len());
+    for &c in s {
+        if c.is_ascii_graphic() && c != b'\\' && c != b'#' {
+            res.push(c as char);
+        } else {
+            res.push_str(&format!("\\{:03o}", c));
+        }
+    }
+    res
+}
+
+/// Parse a manifest type keyword
+fn parse_kind(kind: &str) -> Option<InodeKind> {
+    Some(match kind {
+        "file" => InodeKind::RegularFile,
+        "dir" => InodeKind::Directory,
+        "link" => InodeKind::Symlink,
+        "char" => InodeKind::CharDevice,
+        _ => return None,
+    })
+}
+
+/// Root an mtree path (`.`, `./usr`, `/usr` or `usr`) at `/`
+fn manifest_path(path: &Path) -> PathBuf {
+    Path::new("/")
+        .join(path.strip_prefix(".").unwrap_or(path))
+        .components()
+        .collect()
+}
+
+impl Manifest {
+    /// Parse a manifest from its text
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let syntax = |reason: &str| ParcelError::ManifestSyntax {
+                line:   n + 1,
+                reason: reason.to_string(),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let path = words
+                .next()
+                .and_then(unescape)
+                .ok_or_else(|| syntax("bad path"))?;
+            let mut entry = ManifestEntry {
+                path: manifest_path(Path::new(&path)),
+                ..Default::default()
+            };
+            for word in words {
+                let (key, value) = word
+                    .split_once('=')
+                    .ok_or_else(|| syntax("expected keyword=value"))?;
+                match key {
+                    "type" => {
+                        entry.kind = Some(parse_kind(value).ok_or_else(|| syntax("bad type"))?)
+                    }
+                    "uid" => entry.uid = Some(value.parse().or(Err(syntax("bad uid")))?),
+                    "gid" => entry.gid = Some(value.parse().or(Err(syntax("bad gid")))?),
+                    "mode" => {
+                        entry.mode = Some(
+                            u32::from_str_radix(value, 8)
+                                .ok()
+                                .filter(|mode| mode & !0o7777 == 0)
+                                .ok_or_else(|| syntax("bad mode"))?,
+                        )
+                    }
+                    "device" => {
+                        // BSD mtree prefixes the numbers with their format, e.g. `native,1,3`
+                        let value = value.strip_prefix("native,").unwrap_or(value);
+                        let (major, minor) = value
+                            .split_once(',')
+                            .and_then(|(major, minor)| {
+                                Some((major.parse().ok()?, minor.parse().ok()?))
+                            })
+                            .ok_or_else(|| syntax("bad device"))?;
+                        entry.rdev = Some(libc::makedev(major, minor));
+                    }
+                    "link" => entry.link = Some(unescape(value).ok_or_else(|| syntax("bad link"))?),
+                    // Informational keywords from `parcel-mtree` output
+                    "size" | "sha256digest" => {}
+                    _ => return Err(syntax(&format!("unknown keyword {}", key)).into()),
+                }
+            }
+            entries.push(entry);
+        }
+        Ok(Self { entries })
+    }
+}
+
+impl ParcelHandle {
+    /// Override the ownership and permissions of paths in the parcel. Entries for paths not in
+    /// the parcel create directories, symlinks and character devices, given their type.
+    pub fn apply_manifest(&mut self, manifest: &Manifest) -> Result<()> {
+        for entry in manifest.entries.iter() {
+            let ino = match self.select(entry.path.clone()) {
+                Some(ino) => {
+                    let kind = self.getattr(ino).ok_or(ParcelError::Enoent)?.kind;
+                    if entry.kind.is_some_and(|k| k != kind) {
+                        return Err(
+                            ParcelError::ManifestPath(entry.path.display().to_string()).into()
+                        );
+                    }
+                    ino
+                }
+                None => self.create_from_manifest(entry)?,
+            };
+            let attrs = self.getattr_mut(ino).ok_or(ParcelError::Enoent)?;
+            if let Some(uid) = entry.uid {
+                attrs.uid = uid;
+            }
+            if let Some(gid) = entry.gid {
+                attrs.gid = gid;
+            }
+            if let Some(mode) = entry.mode {
+                attrs.perm = (attrs.perm & !0o7777) | mode;
+            }
+            if let Some(rdev) = entry.rdev {
+                self.set_rdev(ino, rdev)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Create an object described only by a manifest entry
+    fn create_from_manifest(&mut self, entry: &ManifestEntry) -> Result<u64> {
+        let unrepresentable = || ParcelError::ManifestPath(entry.path.display().to_string());
+        let parent = self.ensure_dir(entry.path.parent().ok_or_else(unrepresentable)?)?;
+        let name = entry.path.file_name().ok_or_else(unrepresentable)?;
+        let kind = entry.kind.ok_or_else(unrepresentable)?;
+        let (type_bits, perm) = match kind {
+            InodeKind::Directory => (libc::S_IFDIR, 0o755),
+            InodeKind::Symlink => (libc::S_IFLNK, 0o777),
+            InodeKind::CharDevice => (libc::S_IFCHR, 0o644),
+            _ => return Err(unrepresentable().into()),
+        };
+        let attrs = InodeAttr {
+            perm: type_bits | perm,
+            rdev: entry.rdev.unwrap_or(0),
+            ..Default::default()
+        };
+        let ino = match kind {
+            InodeKind::Directory => self.add_directory(attrs, BTreeMap::new())?,
+            InodeKind::Symlink => {
+                let target = entry.link.clone().ok_or_else(unrepresentable)?;
+                self.add_symlink(target, attrs, BTreeMap::new())?
+            }
+            _ => self.add_char(attrs, BTreeMap::new())?,
+        };
+        self.insert_dirent(parent, name.to_os_string(), ino, kind)?;
+        Ok(ino)
+    }
+}
@@ -1,18 +1,24 @@
+use std::collections::BTreeSet;
+
 use serde::{Deserialize, Serialize};
 
 /// Struct for parcel packaging metadata
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParcelMetadata {
-    pub version: String,
-    pub depends: Vec<String>,
+    pub version:      String,
+    pub depends:      Vec<String>,
+    /// Absolute paths of files that must not be blindly overwritten on upgrade
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub config_files: BTreeSet<String>,
 }
 
 impl ParcelMetadata {
     /// Creates a new empty metadata
     pub fn new() -> Self {
         Self {
-            version: String::new(),
-            depends: Vec::new(),
+            version:      String::new(),
+            depends:      Vec::new(),
+            config_files: BTreeSet::new(),
         }
     }
 }
@@ -0,0 +1,63 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{
+    error::ParcelError,
+    header::{self, Summary},
+    inode::{Inode, InodeContent},
+    metadata::ParcelMetadata,
+    PARCEL_VERSION,
+};
+
+/// Oldest format version that can still be loaded
+pub const OLDEST_VERSION: u32 = 2;
+
+/// A version 2 header: a single YAML document holding every inode
+#[derive(Deserialize)]
+struct HeaderV2 {
+    root_inode: u64,
+    metadata:   ParcelMetadata,
+    inodes:     BTreeMap<u64, Inode>,
+    content:    BTreeMap<u64, InodeContent>,
+}
+
+/// Rewrite a header written by an earlier format version as a current one. The data section
+/// is laid out the same way in every version, so file references carry over unchanged.
+/// Signatures cover the header as written and don't survive the upgrade.
+pub fn upgrade(buf: &[u8], version: u32) -> Result<Vec<u8>> {
+    match version {
+        2 => {
+            let old: HeaderV2 = serde_yaml::from_slice(buf)?;
+            let summary = Summary {
+                version:    PARCEL_VERSION,
+                root_inode: old.root_inode,
+                metadata:   Cow::Owned(old.metadata),
+                signature:  None,
+                alignment:  None,
+            };
+            let (index, records) = header::render_records(&old.inodes, &old.content)?;
+            Ok(header::assemble(
+                &header::render_summary(&summary)?,
+                &index,
+                &records,
+            ))
+        }
+        // Version 3 headers are already indexed; only the file layout around them changed
+        3 => {
+            let summary_bytes = header::summary_bytes(buf);
+            let mut summary: Summary = serde_yaml::from_slice(summary_bytes)?;
+            summary.version = PARCEL_VERSION;
+            summary.signature = None;
+            let mut upgraded = header::render_summary(&summary)?;
+            upgraded.extend_from_slice(&buf[summary_bytes.len()..]);
+            Ok(upgraded)
+        }
+        _ => Err(ParcelError::VersionMismatch {
+            expected: PARCEL_VERSION,
+            found:    version,
+        }
+        .into()),
+    }
+}
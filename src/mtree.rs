@@ -0,0 +1,198 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::Write,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, MetadataExt},
+    },
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{error::ParcelError, manifest::escape, signing, InodeKind, ParcelHandle};
+
+/// mtree keywords describing one object, in output order
+type Keywords = Vec<(&'static str, String)>;
+
+/// A difference between an installed root and the parcel it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// The path is missing from the installed root
+    Missing(PathBuf),
+    /// The installed object differs in one keyword
+    Changed {
+        /// Path of the object
+        path:     PathBuf,
+        /// mtree keyword that differs
+        keyword:  &'static str,
+        /// Value recorded in the parcel
+        expected: String,
+        /// Value found on disk
+        found:    String,
+    },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::Missing(path) => write!(f, "{}: missing", path.display()),
+            Drift::Changed {
+                path,
+                keyword,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: {} (expected {}, found {})",
+                path.display(),
+                keyword,
+                expected,
+                found
+            ),
+        }
+    }
+}
+
+fn type_name(kind: InodeKind) -> &'static str {
+    match kind {
+        InodeKind::Directory => "dir",
+        InodeKind::RegularFile => "file",
+        InodeKind::Symlink => "link",
+        InodeKind::CharDevice => "char",
+        InodeKind::Whiteout => "whiteout",
+    }
+}
+
+fn device(rdev: u64) -> String {
+    format!("native,{},{}", libc::major(rdev), libc::minor(rdev))
+}
+
+/// Describe an object on disk with the same keywords the parcel records
+fn disk_keywords(path: &Path) -> Result<Keywords> {
+    let meta = fs::symlink_metadata(path)?;
+    let file_type = meta.file_type();
+    let kind = if file_type.is_dir() {
+        "dir"
+    } else if file_type.is_file() {
+        "file"
+    } else if file_type.is_symlink() {
+        "link"
+    } else if file_type.is_char_device() {
+        "char"
+    } else if file_type.is_block_device() {
+        "block"
+    } else if file_type.is_fifo() {
+        "fifo"
+    } else {
+        "socket"
+    };
+    let mut keywords: Keywords = vec![("type", kind.into())];
+    if kind != "link" {
+        keywords.push(("mode", format!("{:04o}", meta.mode() & 0o7777)));
+    }
+    keywords.push(("uid", meta.uid().to_string()));
+    keywords.push(("gid", meta.gid().to_string()));
+    match kind {
+        "file" => {
+            keywords.push(("size", meta.len().to_string()));
+            keywords.push((
+                "sha256digest",
+                signing::file_digest(&mut File::open(path)?, 0, meta.len())?,
+            ));
+        }
+        "link" => keywords.push(("link", escape(fs::read_link(path)?.as_os_str().as_bytes()))),
+        "char" => keywords.push(("device", device(meta.rdev()))),
+        _ => {}
+    }
+    Ok(keywords)
+}
+
+impl ParcelHandle {
+    /// Describe an object in the parcel with mtree keywords
+    fn mtree_keywords(&mut self, ino: u64) -> Result<Keywords> {
+        let attr = self.getattr(ino).ok_or(ParcelError::Enoent)?;
+        let mut keywords: Keywords = vec![("type", type_name(attr.kind).into())];
+        if attr.kind != InodeKind::Symlink {
+            keywords.push(("mode", format!("{:04o}", attr.perm & 0o7777)));
+        }
+        keywords.push(("uid", attr.uid.to_string()));
+        keywords.push(("gid", attr.gid.to_string()));
+        match attr.kind {
+            InodeKind::RegularFile => {
+                keywords.push(("size", attr.size.to_string()));
+                keywords.push(("sha256digest", self.digest(ino)?));
+            }
+            InodeKind::Symlink => keywords.push((
+                "link",
+                escape(&self.readlink(ino).ok_or(ParcelError::Enoent)?),
+            )),
+            InodeKind::CharDevice => keywords.push(("device", device(attr.rdev as u64))),
+            _ => {}
+        }
+        Ok(keywords)
+    }
+
+    /// Every object in the parcel with its path, starting with the root
+    fn mtree_entries(&self) -> Result<Vec<(u64, PathBuf)>> {
+        let mut entries = vec![(1, PathBuf::new())];
+        for (ino, _, path) in self.walk(1).ok_or(ParcelError::NotDirectory)? {
+            entries.push((ino, path));
+        }
+        Ok(entries)
+    }
+
+    /// Write a BSD mtree manifest of the parcel
+    pub fn write_mtree<W: Write>(&mut self, mut writer: W) -> Result<()> {
+        writeln!(writer, "#mtree")?;
+        for (ino, path) in self.mtree_entries()? {
+            let path = match path.as_os_str().is_empty() {
+                true => PathBuf::from("."),
+                false => Path::new(".").join(path),
+            };
+            write!(writer, "{}", escape(path.as_os_str().as_bytes()))?;
+            for (keyword, value) in self.mtree_keywords(ino)? {
+                write!(writer, " {}={}", keyword, value)?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Compare the parcel's contents against a root it was extracted to. Objects in the root
+    /// that aren't in the parcel are ignored.
+    pub fn check_installed(&mut self, root: &Path) -> Result<Vec<Drift>> {
+        let mut drift = Vec::new();
+        // The root directory itself belongs to whatever the parcel was extracted into
+        for (ino, path) in self.mtree_entries()?.into_iter().skip(1) {
+            let expected = self.mtree_keywords(ino)?;
+            let installed = root.join(&path);
+            if fs::symlink_metadata(&installed).is_err() {
+                drift.push(Drift::Missing(Path::new("/").join(path)));
+                continue;
+            }
+            let found = disk_keywords(&installed)?;
+            for (keyword, value) in expected {
+                let on_disk = found
+                    .iter()
+                    .find(|(k, _)| *k == keyword)
+                    .map_or_else(|| "none".to_string(), |(_, v)| v.clone());
+                if value != on_disk {
+                    drift.push(Drift::Changed {
+                        path: Path::new("/").join(&path),
+                        keyword,
+                        expected: value,
+                        found: on_disk,
+                    });
+                    // Nothing else is comparable between objects of different types
+                    if keyword == "type" {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(drift)
+    }
+}
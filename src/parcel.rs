@@ -1,27 +1,38 @@
 use std::{
+    borrow::Cow,
     cmp::{max, min, Ordering},
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     ffi::OsString,
     fmt::Debug,
     fs,
     fs::File,
     io::{self, BufRead, Read, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
     path::{Path, PathBuf},
 };
 
 use anyhow::Result;
+use ed25519_dalek::{SigningKey, VerifyingKey};
 use lexiclean::Lexiclean;
-use serde::{Deserialize, Serialize};
 
 use crate::{
+    copy,
     error::ParcelError,
+    header::{self, Layout, LazyHeader, Summary},
     inode::{FileReference, Inode, InodeAttr, InodeContent, InodeKind},
+    journal::{self, Patch},
+    lock,
     metadata::ParcelMetadata,
+    migrate,
+    positional::PositionalReader,
+    signing,
+    sparse::{self, Segment},
+    spec::{self, Violation},
     FileAttr, PARCEL_VERSION, ROOT_ATTRS,
 };
 
 /// Temporarily holds a file we want to add to the parcel
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum FileAdd {
     /// We want to add a file by its literal contents
     Bytes(Vec<u8>),
@@ -31,7 +42,43 @@ pub enum FileAdd {
     Empty,
 }
 
-pub trait FileBacking: BufRead + Write + Seek {}
+/// Storage a parcel is loaded from and stored to
+pub trait FileBacking: BufRead + Write + Seek {
+    /// The file holding the parcel, if any, so file data can be copied within the kernel
+    fn file(&self) -> Option<&File> {
+        None
+    }
+    /// Make everything written so far durable
+    fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        match self.file() {
+            Some(file) => file.sync_data(),
+            None => Ok(()),
+        }
+    }
+    /// Truncate the backing to `len` bytes, returning false if it can't be truncated
+    fn set_len(&mut self, len: u64) -> io::Result<bool> {
+        match self.file() {
+            Some(file) => file.set_len(len).map(|_| true),
+            None => Ok(false),
+        }
+    }
+    /// Whether the backing can be written to, so that an interrupted store can be finished
+    fn writable(&self) -> io::Result<bool> {
+        match self.file() {
+            Some(file) => lock::writable(file),
+            None => Ok(true),
+        }
+    }
+    /// Lock the backing against other processes, shared if it is read-only and exclusive
+    /// otherwise. Returns false if `wait` is false and the lock is held elsewhere.
+    fn lock(&mut self, wait: bool) -> io::Result<bool> {
+        match self.file() {
+            Some(file) => lock::lock(file, wait),
+            None => Ok(true),
+        }
+    }
+}
 
 impl Debug for dyn FileBacking {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -54,24 +101,38 @@ impl ParcelHandle {
             backing: None,
         }
     }
-    /// Set the handle's backing file
-    pub fn set_file(&mut self, f: Box<dyn FileBacking>) {
-        self.backing = Some(f)
+    /// Set the handle's backing file, waiting for any other process using it to finish
+    pub fn set_file(&mut self, f: Box<dyn FileBacking>) -> Result<()> {
+        self.lock_file(f, true)
+    }
+    /// Set the handle's backing file, failing with [`ParcelError::Locked`] if another process
+    /// is using it
+    pub fn try_set_file(&mut self, f: Box<dyn FileBacking>) -> Result<()> {
+        self.lock_file(f, false)
     }
 
-    /// Load a parcel from disk
-    pub fn load(mut f: Box<dyn FileBacking>) -> Result<Self> {
-        Ok(Self {
-            parcel:  Parcel::load(&mut f)?,
-            backing: Some(f),
-        })
+    /// Load a parcel from disk, waiting for any process writing it to finish
+    pub fn load(f: Box<dyn FileBacking>) -> Result<Self> {
+        Self::load_locked(f, true)
+    }
+    /// Load a parcel from disk, failing with [`ParcelError::Locked`] if another process is
+    /// writing it
+    pub fn try_load(f: Box<dyn FileBacking>) -> Result<Self> {
+        Self::load_locked(f, false)
+    }
+    /// Load a parcel from disk, refusing it unless it is signed by the given key
+    pub fn load_verified(f: Box<dyn FileBacking>, key: &VerifyingKey) -> Result<Self> {
+        let mut handle = Self::load(f)?;
+        handle.verify_signature(key)?;
+        Ok(handle)
     }
     /// Write a parcel out to disk
     pub fn store(&mut self) -> Result<()> {
         self.parcel.store(
             self.backing
                 .as_mut()
-                .expect("Writing parcel with no backing file"),
+                .expect("Writing parcel with no backing file")
+                .as_mut(),
         )
     }
     /// Add a file to the parcel
@@ -83,6 +144,36 @@ impl ParcelHandle {
     ) -> Result<u64> {
         self.parcel.add_file(from, attrs, xattrs)
     }
+    /// Add a file whose contents have already been hashed
+    pub(crate) fn add_file_digest(
+        &mut self,
+        from: FileAdd,
+        digest: String,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
+        self.parcel
+            .add_file_digest(from, Some(digest), attrs, xattrs)
+    }
+    /// Add a file by copying `size` bytes from `reader` straight into the data section
+    pub(crate) fn add_file_reader<R: Read>(
+        &mut self,
+        reader: &mut R,
+        size: u64,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
+        self.parcel.add_file_reader(
+            self.backing
+                .as_mut()
+                .expect("Writing parcel with no backing file")
+                .as_mut(),
+            reader,
+            size,
+            attrs,
+            xattrs,
+        )
+    }
     /// Reallocatge a file to allow it to grow
     pub fn realloc_reserved(&mut self, ino: u64, capacity: u64) -> Result<()> {
         self.parcel.realloc_reserved(
@@ -94,7 +185,11 @@ impl ParcelHandle {
         )
     }
     /// Add a directory to the parcel
-    pub fn add_directory(&mut self, attrs: InodeAttr, xattrs: BTreeMap<OsString, Vec<u8>>) -> u64 {
+    pub fn add_directory(
+        &mut self,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
         self.parcel.add_directory(attrs, xattrs)
     }
     /// Add a symlink to the parcel
@@ -106,7 +201,8 @@ impl ParcelHandle {
     ) -> Result<u64> {
         self.parcel.add_symlink(target, attrs, xattrs)
     }
-    /// Add a hard link to an existing path in the parcel
+    /// Add a hard link to an existing path in the parcel. The link is counted once the inode
+    /// is inserted under its new name.
     pub fn add_hardlink(&mut self, target: OsString) -> Result<u64> {
         self.parcel.add_hardlink(target)
     }
@@ -129,6 +225,31 @@ impl ParcelHandle {
             size,
         )
     }
+    /// Copy the contents of a file into `dest`, within the kernel if the parcel is backed by a
+    /// file. Returns the number of bytes copied.
+    pub fn copy_to<W: Write + AsRawFd>(
+        &mut self,
+        ino: u64,
+        offset: u64,
+        size: Option<u64>,
+        dest: &mut W,
+    ) -> Result<u64> {
+        let backing = self
+            .backing
+            .as_mut()
+            .expect("Reading from parcel with no backing file");
+        match (backing.file(), self.parcel.data_span(ino, offset, size)) {
+            (Some(file), Some((position, len))) => {
+                copy::copy_range(file, position, len, dest)?;
+                Ok(len)
+            }
+            _ => {
+                let buf = self.parcel.read(backing, ino, offset, size)?;
+                dest.write_all(&buf)?;
+                Ok(buf.len() as u64)
+            }
+        }
+    }
     /// Write to a file
     pub fn write(&mut self, ino: u64, offset: u64, buf: &[u8]) -> Result<u64> {
         self.parcel.write(
@@ -152,7 +273,11 @@ impl ParcelHandle {
         )
     }
     /// Add a character device to the parcel
-    pub fn add_char(&mut self, attrs: InodeAttr, xattrs: BTreeMap<OsString, Vec<u8>>) -> u64 {
+    pub fn add_char(
+        &mut self,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
         self.parcel.add_char(attrs, xattrs)
     }
     /// Insert an entry to a directory mapping a filename to an inode
@@ -177,6 +302,10 @@ impl ParcelHandle {
     pub fn getattr_mut(&mut self, ino: u64) -> Option<&mut InodeAttr> {
         self.parcel.getattr_mut(ino)
     }
+    /// Set the device number of an inode, keeping a character device's contents in step
+    pub fn set_rdev(&mut self, ino: u64, rdev: u64) -> Result<()> {
+        self.parcel.set_rdev(ino, rdev)
+    }
     /// Check if an inode exists
     pub fn exists(&self, ino: u64) -> bool {
         self.parcel.exists(ino)
@@ -185,6 +314,10 @@ impl ParcelHandle {
     pub fn readdir(&self, ino: u64) -> Option<Vec<(u64, InodeKind, String)>> {
         self.parcel.readdir(ino)
     }
+    /// List everything beneath a directory, parents before children, with paths relative to it
+    pub fn walk(&self, ino: u64) -> Option<Vec<(u64, InodeKind, PathBuf)>> {
+        self.parcel.walk(ino)
+    }
     /// Get the inode number of an object by name within a directory
     pub fn lookup(&self, parent: u64, name: &str) -> Option<u64> {
         self.parcel.lookup(parent, name)
@@ -197,33 +330,310 @@ impl ParcelHandle {
     pub fn getxattrs(&self, ino: u64) -> Option<BTreeMap<OsString, Vec<u8>>> {
         self.parcel.getxattrs(ino)
     }
+    /// Get a mutable ref to the extended attributes of an inode
+    pub fn getxattrs_mut(&mut self, ino: u64) -> Option<&mut BTreeMap<OsString, Vec<u8>>> {
+        self.parcel.getxattrs_mut(ino)
+    }
     /// Get a mutable reference to the parcel's metadata
     pub fn metadata(&mut self) -> &mut ParcelMetadata {
         &mut self.parcel.metadata
     }
+    /// Mark a regular file as a config file, to be protected on upgrade
+    pub fn mark_config(&mut self, path: PathBuf) -> Result<()> {
+        self.parcel.mark_config(path)
+    }
+    /// Check whether a path is marked as a config file
+    pub fn is_config(&self, path: PathBuf) -> bool {
+        self.parcel.is_config(path)
+    }
+    /// Get the (offset, length) of each run of data in a file with holes, or `None` if it has none
+    pub fn segments(&self, ino: u64) -> Option<Vec<(u64, u64)>> {
+        self.parcel.segments(ino)
+    }
+    /// Get the sha256 digest of a file's contents
+    pub fn digest(&mut self, ino: u64) -> Result<String> {
+        self.parcel.digest(
+            self.backing
+                .as_mut()
+                .expect("Reading from parcel with no backing file"),
+            ino,
+        )
+    }
+    /// Get the digests of this parcel's config files, keyed by path
+    pub fn config_digests(&mut self) -> Result<BTreeMap<String, String>> {
+        self.parcel.config_digests(
+            self.backing
+                .as_mut()
+                .expect("Reading from parcel with no backing file"),
+        )
+    }
+    /// Record a digest of every file and sign the header. Takes effect on the next store.
+    pub fn sign(&mut self, key: &SigningKey) -> Result<()> {
+        self.parcel.sign(
+            self.backing
+                .as_mut()
+                .expect("Reading from parcel with no backing file"),
+            key,
+        )
+    }
+    /// Check the header signature and every file's contents against the given key
+    pub fn verify_signature(&mut self, key: &VerifyingKey) -> Result<()> {
+        self.parcel.verify_signature(
+            self.backing
+                .as_mut()
+                .expect("Reading from parcel with no backing file"),
+            key,
+        )
+    }
+    /// Report how many file references share another's data, and how many bytes that saves
+    pub fn dedup_savings(&mut self) -> DedupSavings {
+        self.parcel.dedup_savings()
+    }
+    /// Check the parcel for the inconsistencies [`crate::validate`] reports, as it stands in
+    /// memory
+    pub fn check(&mut self) -> Result<Vec<Violation>> {
+        let data_len = self.parcel.data_len(self.backing.as_mut())?;
+        self.parcel.check(data_len)
+    }
+    /// Repair what [`ParcelHandle::check`] finds where no data would be lost, moving inodes
+    /// that can't be reached into `/lost+found`. Takes effect on the next store. Returns the
+    /// problems that are left.
+    pub fn repair(&mut self) -> Result<Vec<Violation>> {
+        let data_len = self.parcel.data_len(self.backing.as_mut())?;
+        self.parcel.repair(data_len)
+    }
+    /// Start each file's data added from now on, and the data section if the parcel hasn't been
+    /// stored yet, at a multiple of `alignment` bytes (such as [`crate::BLOCK_SIZE`]), so file
+    /// data can be reflinked or mapped straight from the parcel. `None` packs data without gaps.
+    pub fn set_alignment(&mut self, alignment: Option<u64>) {
+        self.parcel.alignment = alignment.filter(|a| *a > 1)
+    }
+    /// Lock a backing file and make it the handle's
+    fn lock_file(&mut self, mut f: Box<dyn FileBacking>, wait: bool) -> Result<()> {
+        if !f.lock(wait)? {
+            return Err(ParcelError::Locked.into());
+        }
+        self.backing = Some(f);
+        Ok(())
+    }
+    /// Lock a backing file, finish any interrupted store, and load the parcel from it
+    fn load_locked(mut f: Box<dyn FileBacking>, wait: bool) -> Result<Self> {
+        if !f.lock(wait)? {
+            return Err(ParcelError::Locked.into());
+        }
+        journal::recover(f.as_mut())?;
+        f.seek(SeekFrom::Start(0))?;
+        Ok(Self {
+            parcel:  Parcel::load(&mut f)?,
+            backing: Some(f),
+        })
+    }
+    /// Remember the current state for a transaction to return to
+    pub(crate) fn savepoint(&mut self) {
+        self.parcel.savepoint()
+    }
+    /// Keep the changes made since the last savepoint
+    pub(crate) fn commit_savepoint(&mut self) {
+        self.parcel.commit_savepoint()
+    }
+    /// Undo the changes made since the last savepoint
+    pub(crate) fn rollback_savepoint(&mut self) {
+        self.parcel.rollback_savepoint()
+    }
+    /// Get the position of a file's data within the backing file, if it is stored contiguously
+    pub fn data_position(&self, ino: u64) -> Option<u64> {
+        self.parcel.data_position(ino)
+    }
+    /// Get the format version the parcel was loaded from, if it was written by an earlier
+    /// version and hasn't been stored in the current format since
+    pub fn upgraded_from(&self) -> Option<u32> {
+        self.parcel.upgraded_from
+    }
+    /// Turn the handle into a read-only view that many threads can read from at once. The
+    /// parcel must be backed by a file and have no unstored files.
+    pub fn into_view(self) -> Result<ParcelView> {
+        if !self.parcel.on_disk {
+            return Err(ParcelError::NotStored.into());
+        }
+        let backing = self.backing.expect("Viewing parcel with no backing file");
+        let file = backing
+            .file()
+            .ok_or(ParcelError::NotFileBacked)?
+            .try_clone()?;
+        // Closing the backing's descriptor gives up the process's lock, so take it again
+        drop(backing);
+        lock::lock(&file, true)?;
+        Ok(ParcelView {
+            parcel: self.parcel,
+            file,
+        })
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Parcel {
-    version:     u32,
+/// A read-only view of a stored parcel that can be shared between threads. File data is read
+/// with positional reads, so concurrent readers never contend for a file position.
+#[derive(Debug)]
+pub struct ParcelView {
+    parcel: Parcel,
+    file:   File,
+}
+
+impl ParcelView {
+    /// Get the inode number for a path
+    pub fn select(&self, path: PathBuf) -> Option<u64> {
+        self.parcel.select(path)
+    }
+    /// Read the contents of a file
+    pub fn read(&self, ino: u64, offset: u64, size: Option<u64>) -> Result<Vec<u8>> {
+        self.parcel
+            .read(&mut PositionalReader::new(&self.file), ino, offset, size)
+    }
+    /// Copy the contents of a file into `dest`, within the kernel where possible. Returns the
+    /// number of bytes copied.
+    pub fn copy_to<W: Write + AsRawFd>(
+        &self,
+        ino: u64,
+        offset: u64,
+        size: Option<u64>,
+        dest: &mut W,
+    ) -> Result<u64> {
+        match self.parcel.data_span(ino, offset, size) {
+            Some((position, len)) => {
+                copy::copy_range(&self.file, position, len, dest)?;
+                Ok(len)
+            }
+            None => {
+                let buf = self.read(ino, offset, size)?;
+                dest.write_all(&buf)?;
+                Ok(buf.len() as u64)
+            }
+        }
+    }
+    /// Get the attributes of an inode
+    pub fn getattr(&self, ino: u64) -> Option<FileAttr> {
+        self.parcel.getattr(ino)
+    }
+    /// Check if an inode exists
+    pub fn exists(&self, ino: u64) -> bool {
+        self.parcel.exists(ino)
+    }
+    /// Read the contents of a directory
+    pub fn readdir(&self, ino: u64) -> Option<Vec<(u64, InodeKind, String)>> {
+        self.parcel.readdir(ino)
+    }
+    /// List everything beneath a directory, parents before children, with paths relative to it
+    pub fn walk(&self, ino: u64) -> Option<Vec<(u64, InodeKind, PathBuf)>> {
+        self.parcel.walk(ino)
+    }
+    /// Get the inode number of an object by name within a directory
+    pub fn lookup(&self, parent: u64, name: &str) -> Option<u64> {
+        self.parcel.lookup(parent, name)
+    }
+    /// Get the target of a symlink
+    pub fn readlink(&self, ino: u64) -> Option<Vec<u8>> {
+        self.parcel.readlink(ino)
+    }
+    /// Get the extended attributes of an inode
+    pub fn getxattrs(&self, ino: u64) -> Option<BTreeMap<OsString, Vec<u8>>> {
+        self.parcel.getxattrs(ino)
+    }
+    /// Get the parcel's metadata
+    pub fn metadata(&self) -> &ParcelMetadata {
+        &self.parcel.metadata
+    }
+    /// Get the (offset, length) of each run of data in a file with holes, or `None` if it has none
+    pub fn segments(&self, ino: u64) -> Option<Vec<(u64, u64)>> {
+        self.parcel.segments(ino)
+    }
+    /// Get the sha256 digest of a file's contents
+    pub fn digest(&self, ino: u64) -> Result<String> {
+        self.parcel
+            .digest(&mut PositionalReader::new(&self.file), ino)
+    }
+    /// Where a range of a file is stored, if it is stored contiguously
+    #[cfg(feature = "tokio")]
+    pub(crate) fn data_span(&self, ino: u64, offset: u64, size: Option<u64>) -> Option<(u64, u64)> {
+        self.parcel.data_span(ino, offset, size)
+    }
+    /// The file holding the parcel
+    #[cfg(feature = "tokio")]
+    pub(crate) fn file(&self) -> &File {
+        &self.file
+    }
+}
+
+/// Space saved by sharing identical file contents
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DedupSavings {
+    /// File references that point at another file's data
+    pub files: u64,
+    /// Bytes that would otherwise have been stored again
+    pub bytes: u64,
+}
+
+/// A span of the data section, possibly shared by several files with identical contents
+#[derive(Debug, Clone)]
+struct Extent {
+    refs:   u64,
+    size:   u64,
+    /// Digest of the contents, while they remain as added and can be shared further
+    digest: Option<String>,
+}
+
+/// What an open transaction has changed, so a rollback can put it back. Inodes and extents are
+/// saved as they're first touched, so beginning a transaction doesn't copy the parcel.
+#[derive(Debug, Clone)]
+struct Savepoint {
     root_inode:  u64,
     metadata:    ParcelMetadata,
-    inodes:      BTreeMap<u64, Inode>,
-    content:     BTreeMap<u64, InodeContent>,
-    #[serde(skip)]
-    file_offset: Option<u64>,
-    #[serde(skip)]
+    signature:   Option<String>,
     next_inode:  u64,
-    #[serde(skip)]
     next_offset: u64,
-    #[serde(skip)]
-    to_add:      BTreeMap<u64, FileAdd>,
-    #[serde(skip)]
     on_disk:     bool,
+    /// Each touched inode as it was, with any file data waiting to be added, or `None` if it
+    /// didn't exist
+    inodes:      BTreeMap<u64, Option<(Inode, InodeContent, Option<FileAdd>)>>,
+    /// Each touched extent as it was, or `None` if it didn't exist
+    extents:     BTreeMap<u64, Option<Extent>>,
+    /// Each touched digest's extent as it was, or `None` if it had none
+    digests:     BTreeMap<String, Option<u64>>,
+    /// The loaded header, if its records were decoded during the outermost transaction
+    lazy:        Option<LazyHeader>,
+    /// The savepoint of the enclosing transaction
+    outer:       Option<Box<Savepoint>>,
+}
+
+#[derive(Debug, Clone)]
+struct Parcel {
+    version:       u32,
+    root_inode:    u64,
+    metadata:      ParcelMetadata,
+    signature:     Option<String>,
+    inodes:        BTreeMap<u64, Inode>,
+    content:       BTreeMap<u64, InodeContent>,
+    /// Records not yet decoded from a loaded header. While set, `inodes` and `content` are empty.
+    lazy:          Option<LazyHeader>,
+    file_offset:   Option<u64>,
+    /// Offset of the header's slot within the data section, and its capacity
+    header_slot:   Option<(u64, u64)>,
+    next_inode:    u64,
+    next_offset:   u64,
+    to_add:        BTreeMap<u64, FileAdd>,
+    on_disk:       bool,
+    /// Extents with nonzero capacity, keyed by offset
+    extents:       BTreeMap<u64, Extent>,
+    /// Offsets of shareable extents, keyed by digest
+    by_digest:     BTreeMap<String, u64>,
+    /// Boundary to start the data section and new extents on, if any
+    alignment:     Option<u64>,
+    /// State to return to if the innermost open transaction is rolled back
+    savepoint:     Option<Box<Savepoint>>,
+    /// Format version the parcel was written in, if it is older and hasn't been stored since
+    upgraded_from: Option<u32>,
 }
 
 fn get_parcel_version(buf: &[u8]) -> Result<u32> {
-    let contents: serde_yaml::Mapping = serde_yaml::from_slice(buf)?;
+    let contents: serde_yaml::Mapping = serde_yaml::from_slice(header::summary_bytes(buf))?;
     let version = contents
         .get(&serde_yaml::Value::String("version".to_string()))
         .ok_or(ParcelError::NoVersion)?;
@@ -234,19 +644,69 @@ fn get_parcel_version(buf: &[u8]) -> Result<u32> {
     }
 }
 
+/// Write the contents of a file being added, as they are laid out in the data section
+fn write_added<W: Write + ?Sized>(
+    val: &FileAdd,
+    file: &FileReference,
+    output: &mut W,
+) -> Result<()> {
+    let written = match (val, &file.segments) {
+        (FileAdd::Bytes(content), _) => {
+            output.write_all(content)?;
+            content.len() as u64
+        }
+        (FileAdd::Name(name), Some(segments)) => {
+            sparse::copy_segments(&mut File::open(name)?, segments, output)?
+        }
+        (FileAdd::Name(name), None) => io::copy(&mut File::open(name)?, output)?,
+        (FileAdd::Empty, _) => 0,
+    };
+    assert_eq!(written, file.capacity);
+    Ok(())
+}
+
+/// Hash a file's contents as stored in the data section
+fn stored_digest<R: Read + Seek>(
+    reader: &mut R,
+    data_offset: u64,
+    file: &FileReference,
+) -> Result<String> {
+    match &file.segments {
+        Some(segments) => sparse::digest(reader, data_offset + file.offset, segments, file.size),
+        None => signing::file_digest(reader, data_offset + file.offset, file.size),
+    }
+}
+
+/// Root a path at `/` and normalize it, so equivalent spellings compare equal
+fn absolute_path(path: PathBuf) -> PathBuf {
+    match path.has_root() {
+        true => path,
+        false => Path::new("/").join(path),
+    }
+    .lexiclean()
+}
+
 impl Parcel {
     fn new() -> Parcel {
         let mut parcel = Parcel {
-            version:     PARCEL_VERSION,
-            root_inode:  1,
-            metadata:    ParcelMetadata::new(),
-            inodes:      BTreeMap::new(),
-            content:     BTreeMap::new(),
-            file_offset: None,
-            next_inode:  1,
-            next_offset: 0,
-            to_add:      BTreeMap::new(),
-            on_disk:     false,
+            version:       PARCEL_VERSION,
+            root_inode:    1,
+            metadata:      ParcelMetadata::new(),
+            signature:     None,
+            inodes:        BTreeMap::new(),
+            content:       BTreeMap::new(),
+            lazy:          None,
+            file_offset:   None,
+            header_slot:   None,
+            next_inode:    1,
+            next_offset:   0,
+            to_add:        BTreeMap::new(),
+            on_disk:       false,
+            extents:       BTreeMap::new(),
+            by_digest:     BTreeMap::new(),
+            alignment:     None,
+            savepoint:     None,
+            upgraded_from: None,
         };
 
         parcel.inodes.insert(
@@ -267,7 +727,7 @@ impl Parcel {
     }
 
     fn load<R: BufRead + Seek>(input: &mut R) -> Result<Parcel> {
-        let mut res: Parcel;
+        let res: Parcel;
 
         let mut magic: [u8; 4] = [0; 4];
 
@@ -275,109 +735,693 @@ impl Parcel {
 
         match &magic {
             b"413\n" => {
-                let mut buf: Vec<u8> = Vec::new();
-                let mut buf_size = 0;
-                loop {
-                    input.read_until(0xA, &mut buf)?;
-                    if buf.len() == buf_size {
-                        panic!();
+                let layout = input.fill_buf()?.first() == Some(&b'@');
+                input.seek(SeekFrom::Start(4))?;
+                let (mut buf, file_offset, header_slot) = match layout {
+                    true => {
+                        let mut line = [0u8; Layout::LEN as usize];
+                        input.read_exact(&mut line)?;
+                        let layout = Layout::parse(&line)?;
+                        input.seek(SeekFrom::Start(layout.header))?;
+                        let mut buf = vec![0u8; layout.len as usize];
+                        input.read_exact(&mut buf)?;
+                        let slot = (layout.header - layout.data, layout.capacity);
+                        (buf, layout.data, Some(slot))
                     }
-                    buf_size = buf.len();
-                    if buf.ends_with(&[0xA, 0x2E, 0x2E, 0x2E, 0xA]) {
-                        break;
+                    // Before version 4 the header came first, followed by the data section
+                    false => {
+                        let mut buf: Vec<u8> = Vec::new();
+                        let mut buf_size = 0;
+                        loop {
+                            input.read_until(0xA, &mut buf)?;
+                            if buf.len() == buf_size {
+                                panic!();
+                            }
+                            buf_size = buf.len();
+                            if buf.ends_with(&[0xA, 0x2E, 0x2E, 0x2E, 0xA]) {
+                                break;
+                            }
+                        }
+                        buf.truncate(buf.len() - 5);
+                        (buf, input.stream_position()?, None)
                     }
-                }
-                buf.truncate(buf.len() - 5);
+                };
 
                 // We must first check the version, as the full deserialization will fail if fields have changed.
                 let ver = get_parcel_version(&buf)?;
-                if ver != PARCEL_VERSION {
+                if !(migrate::OLDEST_VERSION..=PARCEL_VERSION).contains(&ver) {
                     return Err(ParcelError::VersionMismatch {
                         expected: PARCEL_VERSION,
                         found:    ver,
                     }
                     .into());
                 }
+                let upgraded_from = (ver != PARCEL_VERSION).then_some(ver);
+                if upgraded_from.is_some() {
+                    buf = migrate::upgrade(&buf, ver)?;
+                }
 
-                res = serde_yaml::from_slice(&buf)?;
-                res.file_offset = Some(input.stream_position()?);
+                // Only the summary is decoded here; inode records are decoded as they're touched
+                let summary: Summary = serde_yaml::from_slice(header::summary_bytes(&buf))?;
+                res = Parcel {
+                    version: summary.version,
+                    root_inode: summary.root_inode,
+                    metadata: summary.metadata.into_owned(),
+                    signature: summary.signature.map(Cow::into_owned),
+                    inodes: BTreeMap::new(),
+                    content: BTreeMap::new(),
+                    lazy: Some(LazyHeader::new(buf)?),
+                    file_offset: Some(file_offset),
+                    header_slot,
+                    next_inode: 1,
+                    next_offset: 0,
+                    to_add: BTreeMap::new(),
+                    on_disk: true,
+                    extents: BTreeMap::new(),
+                    by_digest: BTreeMap::new(),
+                    alignment: summary.alignment,
+                    savepoint: None,
+                    upgraded_from,
+                };
             }
             _ => panic!("Unknown magic: {:?}", magic),
         }
-        res.on_disk = true;
-        res.next_inode = res
-            .inodes
-            .keys()
-            .max()
-            .expect("Loading parcel with no entries")
-            + 1;
-        res.next_offset = res
-            .content
-            .values()
-            .map(|x| {
-                if let InodeContent::RegularFile(f) = x {
-                    f.offset + f.capacity
-                } else {
-                    0
-                }
-            })
-            .max()
-            .expect("Loading parcel with no entries");
         Ok(res)
     }
 
-    fn store<W: Read + Write + Seek>(&mut self, mut output: W) -> Result<()> {
-        let mut buf = serde_yaml::to_vec(self)?;
-        let mut file_offset = (buf.len() + 4 + 5) as u64;
+    /// Decode every record of a lazily loaded header, so the parcel can be modified
+    fn materialize(&mut self) -> Result<()> {
+        if let Some(lazy) = &self.lazy {
+            // Decode everything before touching the parcel, so a corrupt record leaves it as it was
+            let mut inodes = BTreeMap::new();
+            let mut content = BTreeMap::new();
+            for ino in lazy.inos() {
+                let record = lazy.record(ino)?.ok_or(ParcelError::CorruptHeader)?;
+                inodes.insert(ino, record.inode.into_owned());
+                content.insert(ino, record.content.into_owned());
+            }
+            let lazy = self.lazy.take();
+            self.inodes = inodes;
+            self.content = content;
+            self.next_inode = self.inodes.keys().max().map_or(1, |max| max + 1);
+            self.next_offset = self
+                .content
+                .values()
+                .map(|x| {
+                    if let InodeContent::RegularFile(f) = x {
+                        f.offset + f.capacity
+                    } else {
+                        0
+                    }
+                })
+                .chain(self.header_slot.map(|(slot, capacity)| slot + capacity))
+                .max()
+                .unwrap_or(0);
+            for content in self.content.values() {
+                if let InodeContent::RegularFile(f) = content {
+                    if f.capacity == 0 {
+                        continue;
+                    }
+                    let extent = self.extents.entry(f.offset).or_insert(Extent {
+                        refs:   0,
+                        size:   f.size,
+                        digest: None,
+                    });
+                    extent.refs += 1;
+                    // Files with holes are stored packed, so only dense data can be shared
+                    if let (Some(digest), None) = (&f.digest, &f.segments) {
+                        if extent.refs == 1 {
+                            extent.digest = Some(digest.clone());
+                            self.by_digest.insert(digest.clone(), f.offset);
+                        }
+                    }
+                }
+            }
+            // Open transactions all began before this, so they share where the data ended. Rolling
+            // back the outermost goes back to the undecoded header.
+            let mut saved = self.savepoint.as_deref_mut();
+            while let Some(savepoint) = saved {
+                savepoint.next_inode = self.next_inode;
+                savepoint.next_offset = self.next_offset;
+                if savepoint.outer.is_none() {
+                    savepoint.lazy = lazy;
+                    break;
+                }
+                saved = savepoint.outer.as_deref_mut();
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop one reference to the extent at `offset`, forgetting it once unused
+    fn release(&mut self, offset: u64) {
+        self.touch_extent(offset);
+        let extent = self
+            .extents
+            .get_mut(&offset)
+            .expect("Releasing an untracked extent, parcel is inconsistent");
+        extent.refs -= 1;
+        if extent.refs == 0 {
+            if let Some(digest) = extent.digest.take() {
+                self.touch_digest(&digest);
+                self.by_digest.remove(&digest);
+            }
+            self.extents.remove(&offset);
+        }
+    }
+
+    /// Remember the current state, to return to if the transaction now beginning is rolled back
+    fn savepoint(&mut self) {
+        self.savepoint = Some(Box::new(Savepoint {
+            root_inode:  self.root_inode,
+            metadata:    self.metadata.clone(),
+            signature:   self.signature.clone(),
+            next_inode:  self.next_inode,
+            next_offset: self.next_offset,
+            on_disk:     self.on_disk,
+            inodes:      BTreeMap::new(),
+            extents:     BTreeMap::new(),
+            digests:     BTreeMap::new(),
+            lazy:        None,
+            outer:       self.savepoint.take(),
+        }));
+    }
 
-        if let Some(cur_file_offset) = self.file_offset {
-            match file_offset.cmp(&cur_file_offset) {
-                Ordering::Equal => {}
-                Ordering::Greater => {
-                    // Amortize expansion costs by overexpanding
-                    file_offset = max(file_offset, ((cur_file_offset as f64) * 1.2) as u64);
-                    buf.resize((file_offset - 4 - 5) as usize, b' ');
-                    output.seek(SeekFrom::Start(cur_file_offset))?;
-                    let mut buf = Vec::new();
-                    output.read_to_end(&mut buf)?;
-                    output.seek(SeekFrom::Start(file_offset))?;
-                    output.write_all(&buf)?;
+    /// Keep the changes made since the last savepoint, folding what it saved into the enclosing
+    /// one where that hasn't saved an older state already
+    fn commit_savepoint(&mut self) {
+        if let Some(saved) = self.savepoint.take() {
+            let saved = *saved;
+            self.savepoint = saved.outer;
+            if let Some(outer) = &mut self.savepoint {
+                for (ino, state) in saved.inodes {
+                    outer.inodes.entry(ino).or_insert(state);
+                }
+                for (offset, state) in saved.extents {
+                    outer.extents.entry(offset).or_insert(state);
                 }
-                Ordering::Less => {
-                    // For now, never shrink, just pad the buffer
-                    buf.resize((cur_file_offset - 4 - 5) as usize, b' ');
-                    file_offset = cur_file_offset;
+                for (digest, state) in saved.digests {
+                    outer.digests.entry(digest).or_insert(state);
                 }
             }
         }
+    }
 
-        output.seek(SeekFrom::Start(0))?;
-        output.write_all(b"413\n")?;
-        output.write_all(&buf)?;
-        output.write_all(b"\n...\n")?;
+    /// Undo the changes made since the last savepoint
+    fn rollback_savepoint(&mut self) {
+        if let Some(saved) = self.savepoint.take() {
+            let saved = *saved;
+            self.root_inode = saved.root_inode;
+            self.metadata = saved.metadata;
+            self.signature = saved.signature;
+            self.next_inode = saved.next_inode;
+            self.next_offset = saved.next_offset;
+            self.on_disk = saved.on_disk;
+            self.savepoint = saved.outer;
+            // Nothing had been decoded when the transaction began, so all of it can go
+            if let Some(lazy) = saved.lazy {
+                self.lazy = Some(lazy);
+                self.inodes.clear();
+                self.content.clear();
+                self.to_add.clear();
+                self.extents.clear();
+                self.by_digest.clear();
+                return;
+            }
+            for (ino, state) in saved.inodes {
+                self.to_add.remove(&ino);
+                match state {
+                    Some((inode, content, add)) => {
+                        self.inodes.insert(ino, inode);
+                        self.content.insert(ino, content);
+                        if let Some(add) = add {
+                            self.to_add.insert(ino, add);
+                        }
+                    }
+                    None => {
+                        self.inodes.remove(&ino);
+                        self.content.remove(&ino);
+                    }
+                }
+            }
+            for (offset, state) in saved.extents {
+                match state {
+                    Some(extent) => self.extents.insert(offset, extent),
+                    None => self.extents.remove(&offset),
+                };
+            }
+            for (digest, state) in saved.digests {
+                match state {
+                    Some(offset) => self.by_digest.insert(digest, offset),
+                    None => self.by_digest.remove(&digest),
+                };
+            }
+        }
+    }
 
-        assert_eq!(file_offset, output.stream_position()?);
+    /// Save an inode as it is for an open transaction, before it is first changed
+    fn touch(&mut self, ino: u64) {
+        if let Some(saved) = &mut self.savepoint {
+            saved.inodes.entry(ino).or_insert_with(|| {
+                Some((
+                    self.inodes.get(&ino)?.clone(),
+                    self.content.get(&ino)?.clone(),
+                    self.to_add.get(&ino).cloned(),
+                ))
+            });
+        }
+    }
+
+    /// Save an extent as it is for an open transaction, before it is first changed
+    fn touch_extent(&mut self, offset: u64) {
+        if let Some(saved) = &mut self.savepoint {
+            saved
+                .extents
+                .entry(offset)
+                .or_insert_with(|| self.extents.get(&offset).cloned());
+        }
+    }
 
+    /// Save which extent a digest leads to for an open transaction, before it is first changed
+    fn touch_digest(&mut self, digest: &str) {
+        if let Some(saved) = &mut self.savepoint {
+            if !saved.digests.contains_key(digest) {
+                saved
+                    .digests
+                    .insert(digest.to_string(), self.by_digest.get(digest).copied());
+            }
+        }
+    }
+
+    /// Give a file sole ownership of its data before it is modified, copying the data if it is
+    /// shared, has holes, or is still needed by an open transaction's savepoint. The data no
+    /// longer matches its digest, so it can't be shared again.
+    fn unshare<W: Read + Write + Seek>(&mut self, writer: &mut W, ino: u64) -> Result<()> {
+        let file = match self.content.get(&ino).ok_or(ParcelError::Enoent)? {
+            InodeContent::RegularFile(f) => f.clone(),
+            _ => return Err(ParcelError::NotFile.into()),
+        };
+        self.touch(ino);
+        let shared = file.capacity > 0 && self.extents[&file.offset].refs > 1;
+        let saved = self
+            .savepoint
+            .as_ref()
+            .is_some_and(|saved| file.capacity > 0 && file.offset < saved.next_offset);
+        if file.segments.is_none() && !shared && !saved {
+            self.touch_extent(file.offset);
+            if let Some(digest) = self
+                .extents
+                .get_mut(&file.offset)
+                .and_then(|e| e.digest.take())
+            {
+                self.touch_digest(&digest);
+                self.by_digest.remove(&digest);
+            }
+            if let Some(InodeContent::RegularFile(f)) = self.content.get_mut(&ino) {
+                f.digest = None;
+            }
+            return Ok(());
+        }
+
+        // Copy the data somewhere of its own, filling in any holes
+        let data_offset = self
+            .file_offset
+            .expect("Parcel not properly loaded- no offset stored to data section");
+        let buf = match &file.segments {
+            Some(segments) => {
+                sparse::read_segments(writer, data_offset + file.offset, segments, 0, file.size)?
+            }
+            None => {
+                writer.seek(SeekFrom::Start(data_offset + file.offset))?;
+                let mut buf = vec![0u8; file.size as usize];
+                writer.read_exact(&mut buf)?;
+                buf
+            }
+        };
+        let capacity = max(file.capacity, file.size);
+        let offset = self.align(self.next_offset);
+        self.next_offset = offset + capacity;
+        writer.seek(SeekFrom::Start(data_offset + offset))?;
+        writer.write_all(&buf)?;
+        writer.write_all(&vec![b' '; (capacity - file.size) as usize])?;
+        if file.capacity > 0 {
+            self.release(file.offset);
+        }
+        if capacity > 0 {
+            self.touch_extent(offset);
+            self.extents.insert(
+                offset,
+                Extent {
+                    refs:   1,
+                    size:   file.size,
+                    digest: None,
+                },
+            );
+        }
+        if let Some(InodeContent::RegularFile(f)) = self.content.get_mut(&ino) {
+            f.offset = offset;
+            f.capacity = capacity;
+            f.digest = None;
+            f.segments = None;
+        }
+        Ok(())
+    }
+
+    fn dedup_savings(&self) -> DedupSavings {
+        let mut savings = DedupSavings::default();
+        for extent in self.extents.values() {
+            savings.files += extent.refs - 1;
+            savings.bytes += (extent.refs - 1) * extent.size;
+        }
+        savings
+    }
+
+    /// Length of the data section on disk, if everything the parcel refers to should be in it
+    fn data_len(&self, backing: Option<&mut Box<dyn FileBacking>>) -> Result<Option<u64>> {
+        match (backing, self.file_offset) {
+            (Some(backing), Some(file_offset)) if self.to_add.is_empty() => {
+                let len = backing.seek(SeekFrom::End(0))?;
+                Ok(Some(len.saturating_sub(file_offset)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn check(&mut self, data_len: Option<u64>) -> Result<Vec<Violation>> {
+        self.materialize()?;
+        Ok(spec::check_tree(
+            self.root_inode,
+            &self.inodes,
+            &self.content,
+            data_len,
+            self.header_slot,
+        ))
+    }
+
+    /// Remove dangling entries, correct the kinds of entries, link orphans into `/lost+found`,
+    /// then correct parents and link counts. Problems with file data are left alone.
+    fn repair(&mut self, data_len: Option<u64>) -> Result<Vec<Violation>> {
+        for violation in self.check(data_len)? {
+            let (dir, name, kind) = match violation {
+                Violation::DanglingDirent { dir, name, .. } => (dir, name, None),
+                Violation::DirentKind {
+                    dir, name, actual, ..
+                } => (dir, name, Some(actual)),
+                _ => continue,
+            };
+            self.touch(dir);
+            if let Some(InodeContent::Directory(entries)) = self.content.get_mut(&dir) {
+                match kind {
+                    Some(kind) => {
+                        if let Some(entry) = entries.get_mut(&name) {
+                            entry.1 = kind;
+                        }
+                    }
+                    None => {
+                        entries.remove(&name);
+                    }
+                }
+            }
+        }
+        self.adopt_orphans()?;
+        for violation in self.check(data_len)? {
+            match violation {
+                Violation::WrongParent { ino, expected, .. } => {
+                    self.touch(ino);
+                    self.inodes
+                        .get_mut(&ino)
+                        .expect("Checked inode is missing")
+                        .parent = expected
+                }
+                Violation::WrongNlink { ino, expected, .. } => {
+                    self.touch(ino);
+                    self.inodes
+                        .get_mut(&ino)
+                        .expect("Checked inode is missing")
+                        .attrs
+                        .nlink = expected
+                }
+                _ => (),
+            }
+        }
+        self.check(data_len)
+    }
+
+    /// Link inodes that can't be reached from the root into `/lost+found`, named after their
+    /// inode numbers. Only the top of each unreachable tree is linked; the rest comes with it.
+    fn adopt_orphans(&mut self) -> Result<()> {
+        loop {
+            let orphans: BTreeSet<u64> = self
+                .check(None)?
+                .into_iter()
+                .filter_map(|violation| match violation {
+                    Violation::Orphan { ino } => Some(ino),
+                    _ => None,
+                })
+                .collect();
+            let named: BTreeSet<u64> = orphans
+                .iter()
+                .filter_map(|ino| match &self.content[ino] {
+                    InodeContent::Directory(entries) => Some(entries.values().map(|(t, _)| *t)),
+                    _ => None,
+                })
+                .flatten()
+                .collect();
+            let mut tops: Vec<u64> = orphans.difference(&named).copied().collect();
+            // Directories that only name each other have no top, so break into the cycle
+            if tops.is_empty() {
+                match orphans.first() {
+                    Some(ino) => tops.push(*ino),
+                    None => return Ok(()),
+                }
+            }
+            let lost_found = self.lost_found()?;
+            for ino in tops {
+                let kind = self.inodes[&ino].kind;
+                self.insert_dirent(lost_found, format!("#{}", ino).into(), ino, kind)?;
+            }
+        }
+    }
+
+    /// Find `/lost+found`, creating it if needed
+    fn lost_found(&mut self) -> Result<u64> {
+        match self.lookup(self.root_inode, "lost+found") {
+            Some(ino) => match self.inodes.get(&ino).map(|i| i.kind) {
+                Some(InodeKind::Directory) => Ok(ino),
+                _ => Err(ParcelError::NotDirectory.into()),
+            },
+            None => {
+                let attrs = InodeAttr {
+                    perm: 0o700,
+                    ..ROOT_ATTRS
+                };
+                let ino = self.add_directory(attrs, BTreeMap::new())?;
+                self.insert_dirent(
+                    self.root_inode,
+                    "lost+found".into(),
+                    ino,
+                    InodeKind::Directory,
+                )?;
+                Ok(ino)
+            }
+        }
+    }
+
+    /// Get an inode and its contents, decoding them from the header if necessary
+    fn inode_entry(&self, ino: u64) -> Option<(Cow<'_, Inode>, Cow<'_, InodeContent>)> {
+        match &self.lazy {
+            // A record that can't be decoded is treated as missing; it is reported as corrupt by
+            // anything that needs the whole header
+            Some(lazy) => {
+                let record = lazy.record(ino).ok()??;
+                Some((
+                    Cow::Owned(record.inode.into_owned()),
+                    Cow::Owned(record.content.into_owned()),
+                ))
+            }
+            None => Some((
+                Cow::Borrowed(self.inodes.get(&ino)?),
+                Cow::Borrowed(self.content.get(&ino)?),
+            )),
+        }
+    }
+
+    /// Get the contents of an inode, decoding them from the header if necessary
+    fn inode_content(&self, ino: u64) -> Option<Cow<'_, InodeContent>> {
+        Some(self.inode_entry(ino)?.1)
+    }
+
+    /// All inode numbers, in ascending order
+    fn inos(&self) -> Vec<u64> {
+        match &self.lazy {
+            Some(lazy) => lazy.inos().collect(),
+            None => self.inodes.keys().copied().collect(),
+        }
+    }
+
+    fn summary(&self) -> Summary<'_> {
+        Summary {
+            version:    self.version,
+            root_inode: self.root_inode,
+            metadata:   Cow::Borrowed(&self.metadata),
+            signature:  self.signature.as_deref().map(Cow::Borrowed),
+            alignment:  self.alignment,
+        }
+    }
+
+    /// Round an offset within the data section up to where the parcel's alignment allows data
+    /// to start. Before the first store, the data section itself will be aligned.
+    fn align(&self, offset: u64) -> u64 {
+        let base = self.file_offset.unwrap_or(0);
+        match self.alignment {
+            Some(alignment) => (base + offset).div_ceil(alignment) * alignment - base,
+            None => offset,
+        }
+    }
+
+    /// Find where a range of a file is stored contiguously in the backing file, as its position
+    /// and length
+    fn data_span(&self, ino: u64, offset: u64, size: Option<u64>) -> Option<(u64, u64)> {
+        let content = self.inode_content(ino)?;
+        let file = match content.as_ref() {
+            InodeContent::RegularFile(f) if !self.to_add.contains_key(&ino) => f,
+            _ => return None,
+        };
+        let offset = min(offset, file.size);
+        let len = min(size.unwrap_or(u64::MAX), file.size - offset);
+        let stored = match &file.segments {
+            None => file.offset + offset,
+            Some(segments) => {
+                let mut stored = file.offset;
+                let (start, _) = segments.iter().find(|(start, seg_len)| {
+                    if offset >= *start && offset + len <= start + seg_len {
+                        return true;
+                    }
+                    stored += seg_len;
+                    false
+                })?;
+                stored + offset - start
+            }
+        };
+        Some((self.file_offset? + stored, len))
+    }
+
+    fn data_position(&self, ino: u64) -> Option<u64> {
+        match self.inode_content(ino)?.as_ref() {
+            InodeContent::RegularFile(f)
+                if f.capacity > 0 && f.segments.is_none() && !self.to_add.contains_key(&ino) =>
+            {
+                Some(self.file_offset? + f.offset)
+            }
+            _ => None,
+        }
+    }
+
+    /// The header as covered by the signature: the summary without its signature, then the records.
+    /// The records are rendered again from what the index leads to rather than taken as stored,
+    /// so a verified signature covers exactly the inodes the parcel will serve.
+    fn signed_bytes(&self) -> Result<Vec<u8>> {
+        let mut summary = self.summary();
+        summary.signature = None;
+        let mut buf = header::render_summary(&summary)?;
+        let records = match &self.lazy {
+            Some(lazy) => {
+                let mut inodes = BTreeMap::new();
+                let mut content = BTreeMap::new();
+                for ino in lazy.inos() {
+                    let record = lazy.record(ino)?.ok_or(ParcelError::CorruptHeader)?;
+                    inodes.insert(ino, record.inode.into_owned());
+                    content.insert(ino, record.content.into_owned());
+                }
+                header::render_records(&inodes, &content)?.1
+            }
+            None => header::render_records(&self.inodes, &self.content)?.1,
+        };
+        buf.extend(records);
+        Ok(buf)
+    }
+
+    /// Write the header and any added files. Nothing the previous header refers to is
+    /// overwritten except through the journal, so an interrupted store can't leave the parcel
+    /// unloadable, and file data never moves.
+    fn store(&mut self, output: &mut dyn FileBacking) -> Result<()> {
+        if self.savepoint.is_some() {
+            return Err(ParcelError::TransactionOpen.into());
+        }
+        self.materialize()?;
+        let summary = header::render_summary(&self.summary())?;
+        let (index, records) = header::render_records(&self.inodes, &self.content)?;
+        let mut header = header::assemble(&summary, &index, &records);
+        let len = header.len() as u64;
+        header.extend_from_slice(b"\n...\n");
+        let file_offset = match self.file_offset {
+            Some(file_offset) => file_offset,
+            None => self.align(4 + Layout::LEN),
+        };
+
+        // Added files land past everything the previous header refers to, so they can be written
+        // in place
         for (ino, val) in self.to_add.iter() {
             match &self.content[ino] {
                 InodeContent::RegularFile(file) => {
                     output.seek(SeekFrom::Start(file_offset + file.offset))?;
-                    assert_eq!(
-                        match val {
-                            FileAdd::Bytes(content) => output.write(content)? as u64,
-                            FileAdd::Name(name) => io::copy(&mut File::open(name)?, &mut output)?,
-                            FileAdd::Empty => 0,
-                        },
-                        file.size
-                    );
+                    write_added(val, file, output)?;
                 }
                 _ => panic!(),
             }
         }
+
+        let mut patches: Vec<Patch> = Vec::new();
+        let (slot, capacity) = match self.header_slot {
+            Some((slot, capacity)) if header.len() as u64 <= capacity => {
+                header.resize(capacity as usize, b' ');
+                patches.push((file_offset + slot, header));
+                (slot, capacity)
+            }
+            // The header has outgrown its slot, so it goes in a new one after all the data
+            previous => {
+                // Amortize relocation costs by overexpanding
+                let capacity = max(
+                    header.len() as u64,
+                    previous.map_or(0, |(_, capacity)| capacity + capacity / 5),
+                );
+                header.resize(capacity as usize, b' ');
+                let slot = self.next_offset;
+                self.next_offset += capacity;
+                output.seek(SeekFrom::Start(file_offset + slot))?;
+                output.write_all(&header)?;
+                (slot, capacity)
+            }
+        };
+        let layout = Layout {
+            data: file_offset,
+            header: file_offset + slot,
+            len,
+            capacity,
+        };
+        let mut start = b"413\n".to_vec();
+        start.extend_from_slice(&layout.render());
+        // A header written by an earlier version sits in front of the data section
+        if self.upgraded_from.is_some() {
+            start.resize(max(start.len() as u64, file_offset) as usize, b' ');
+        }
+        patches.push((0, start));
+        journal::commit(output, &patches)?;
+
+        // Blank out a header slot that was left behind, now nothing refers to it
+        if let Some((previous, capacity)) =
+            self.header_slot.filter(|(previous, _)| *previous != slot)
+        {
+            output.seek(SeekFrom::Start(file_offset + previous))?;
+            output.write_all(&vec![b' '; capacity as usize])?;
+            output.flush()?;
+        }
+
         self.to_add.clear();
         self.on_disk = true;
         self.file_offset = Some(file_offset);
-        output.flush()?;
+        self.header_slot = Some((slot, capacity));
+        self.upgraded_from = None;
         Ok(())
     }
 
@@ -387,9 +1431,22 @@ impl Parcel {
         attrs: InodeAttr,
         xattrs: BTreeMap<OsString, Vec<u8>>,
     ) -> Result<u64> {
+        self.add_file_digest(from, None, attrs, xattrs)
+    }
+
+    /// Add a file, hashing its contents unless the digest is already known
+    fn add_file_digest(
+        &mut self,
+        from: FileAdd,
+        digest: Option<String>,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
+        self.materialize()?;
         while self.inodes.contains_key(&self.next_inode) {
             self.next_inode += 1;
         }
+        self.touch(self.next_inode);
 
         self.inodes.insert(
             self.next_inode,
@@ -407,23 +1464,161 @@ impl Parcel {
             FileAdd::Empty => 0,
         };
 
-        if filesize > 0 {
-            self.to_add.insert(self.next_inode, from);
-        }
+        let segments = match &from {
+            FileAdd::Name(name) if filesize > 0 => {
+                sparse::data_segments(&File::open(name)?, filesize)?
+            }
+            _ => None,
+        };
+        let stored = segments.as_deref().map_or(filesize, sparse::stored_len);
+
+        // Files with holes aren't shared, as their data is laid out differently
+        let digest = match &from {
+            _ if filesize == 0 || segments.is_some() => None,
+            _ if digest.is_some() => digest,
+            FileAdd::Bytes(i) => Some(signing::bytes_digest(i)),
+            FileAdd::Name(name) => Some(signing::file_digest(&mut File::open(name)?, 0, filesize)?),
+            FileAdd::Empty => None,
+        };
+
+        // Identical contents share the existing extent rather than being stored again
+        let offset = match digest.as_ref().and_then(|d| self.by_digest.get(d)) {
+            Some(&offset) => {
+                self.touch_extent(offset);
+                self.extents
+                    .get_mut(&offset)
+                    .expect("Digest refers to an untracked extent, parcel is inconsistent")
+                    .refs += 1;
+                offset
+            }
+            None => {
+                let offset = match stored {
+                    0 => self.next_offset,
+                    _ => self.align(self.next_offset),
+                };
+                if stored > 0 {
+                    self.to_add.insert(self.next_inode, from);
+                    self.on_disk = false;
+                    if let Some(digest) = &digest {
+                        self.touch_digest(digest);
+                        self.by_digest.insert(digest.clone(), offset);
+                    }
+                    self.touch_extent(offset);
+                    self.extents.insert(
+                        offset,
+                        Extent {
+                            refs: 1,
+                            size: stored,
+                            digest,
+                        },
+                    );
+                }
+                self.next_offset = offset + stored;
+                offset
+            }
+        };
         self.content.insert(
             self.next_inode,
             InodeContent::RegularFile(FileReference {
-                offset:   self.next_offset,
-                size:     filesize,
-                capacity: filesize,
+                offset,
+                size: filesize,
+                capacity: stored,
+                digest: None,
+                segments,
             }),
         );
-        self.next_offset += filesize;
 
         self.next_inode += 1;
-        if filesize > 0 {
-            self.on_disk = false;
+        Ok(self.next_inode - 1)
+    }
+
+    /// Add a file by copying its contents straight into the data section as they're read, so
+    /// they never have to be held in memory. Contents found to match an existing extent share it,
+    /// and the space they were copied to is used again.
+    fn add_file_reader<R: Read>(
+        &mut self,
+        output: &mut dyn FileBacking,
+        reader: &mut R,
+        size: u64,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
+        self.materialize()?;
+        while self.inodes.contains_key(&self.next_inode) {
+            self.next_inode += 1;
         }
+        self.touch(self.next_inode);
+
+        // The data section of a parcel that hasn't been stored yet starts where storing will put it
+        let file_offset = match self.file_offset {
+            Some(file_offset) => file_offset,
+            None => {
+                let file_offset = self.align(4 + Layout::LEN);
+                self.file_offset = Some(file_offset);
+                file_offset
+            }
+        };
+        let offset = match size {
+            0 => self.next_offset,
+            _ => self.align(self.next_offset),
+        };
+        let end = output.seek(SeekFrom::End(0))?;
+        output.seek(SeekFrom::Start(file_offset + offset))?;
+        let digest = signing::copy_digest(reader, output, size)?;
+
+        let offset = match self.by_digest.get(&digest) {
+            Some(&shared) if size > 0 => {
+                // Give back the space if the copy grew the backing
+                if end <= file_offset + offset {
+                    output.flush()?;
+                    output.set_len(end)?;
+                }
+                self.touch_extent(shared);
+                self.extents
+                    .get_mut(&shared)
+                    .expect("Digest refers to an untracked extent, parcel is inconsistent")
+                    .refs += 1;
+                shared
+            }
+            _ if size > 0 => {
+                self.touch_digest(&digest);
+                self.by_digest.insert(digest.clone(), offset);
+                self.touch_extent(offset);
+                self.extents.insert(
+                    offset,
+                    Extent {
+                        refs: 1,
+                        size,
+                        digest: Some(digest),
+                    },
+                );
+                self.next_offset = offset + size;
+                offset
+            }
+            _ => offset,
+        };
+
+        self.inodes.insert(
+            self.next_inode,
+            Inode {
+                kind: InodeKind::RegularFile,
+                parent: 0,
+                attrs,
+                xattrs,
+            },
+        );
+        self.content.insert(
+            self.next_inode,
+            InodeContent::RegularFile(FileReference {
+                offset,
+                size,
+                capacity: size,
+                digest: None,
+                segments: None,
+            }),
+        );
+
+        self.next_inode += 1;
         Ok(self.next_inode - 1)
     }
 
@@ -433,9 +1628,13 @@ impl Parcel {
         ino: u64,
         capacity: u64,
     ) -> Result<()> {
+        self.materialize()?;
+        self.unshare(writer, ino)?;
+        let aligned_end = self.align(self.next_offset);
         if let InodeContent::RegularFile(inode) =
             self.content.get_mut(&ino).ok_or(ParcelError::Enoent)?
         {
+            let (old_offset, old_capacity) = (inode.offset, inode.capacity);
             match capacity.cmp(&inode.capacity) {
                 Ordering::Equal => (),
                 Ordering::Greater => {
@@ -457,7 +1656,7 @@ impl Parcel {
                         let mut buf = vec![0u8; inode.size as usize];
                         writer.read_exact(&mut buf)?;
 
-                        inode.offset = self.next_offset;
+                        inode.offset = aligned_end;
                         inode.capacity = capacity;
                         self.next_offset = inode.offset + inode.capacity;
 
@@ -473,16 +1672,39 @@ impl Parcel {
                 Ordering::Less => inode.capacity = capacity,
             }
             inode.size = min(inode.size, inode.capacity);
+            let (offset, size) = (inode.offset, inode.size);
+
+            let moved = offset != old_offset;
+            if old_capacity > 0 && (moved || capacity == 0) {
+                self.release(old_offset);
+            }
+            if capacity > 0 && (moved || old_capacity == 0) {
+                self.touch_extent(offset);
+                self.extents.insert(
+                    offset,
+                    Extent {
+                        refs: 1,
+                        size,
+                        digest: None,
+                    },
+                );
+            }
             Ok(())
         } else {
             unimplemented!();
         }
     }
 
-    fn add_directory(&mut self, attrs: InodeAttr, xattrs: BTreeMap<OsString, Vec<u8>>) -> u64 {
+    fn add_directory(
+        &mut self,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
+        self.materialize()?;
         while self.inodes.contains_key(&self.next_inode) {
             self.next_inode += 1;
         }
+        self.touch(self.next_inode);
 
         self.inodes.insert(
             self.next_inode,
@@ -497,7 +1719,7 @@ impl Parcel {
             .insert(self.next_inode, InodeContent::Directory(BTreeMap::new()));
 
         self.next_inode += 1;
-        self.next_inode - 1
+        Ok(self.next_inode - 1)
     }
 
     fn add_symlink(
@@ -506,9 +1728,11 @@ impl Parcel {
         attrs: InodeAttr,
         xattrs: BTreeMap<OsString, Vec<u8>>,
     ) -> Result<u64> {
+        self.materialize()?;
         while self.inodes.contains_key(&self.next_inode) {
             self.next_inode += 1;
         }
+        self.touch(self.next_inode);
 
         self.inodes.insert(
             self.next_inode,
@@ -537,10 +1761,12 @@ impl Parcel {
             .ok_or_else(|| ParcelError::Enoent.into())
     }
 
-    fn add_char(&mut self, attrs: InodeAttr, xattrs: BTreeMap<OsString, Vec<u8>>) -> u64 {
+    fn add_char(&mut self, attrs: InodeAttr, xattrs: BTreeMap<OsString, Vec<u8>>) -> Result<u64> {
+        self.materialize()?;
         while self.inodes.contains_key(&self.next_inode) {
             self.next_inode += 1;
         }
+        self.touch(self.next_inode);
 
         self.inodes.insert(
             self.next_inode,
@@ -555,7 +1781,7 @@ impl Parcel {
             .insert(self.next_inode, InodeContent::Char(attrs.rdev));
 
         self.next_inode += 1;
-        self.next_inode - 1
+        Ok(self.next_inode - 1)
     }
 
     fn insert_dirent(
@@ -565,40 +1791,58 @@ impl Parcel {
         child: u64,
         kind: InodeKind,
     ) -> Result<()> {
-        match self.content.get_mut(&parent).unwrap() {
+        self.materialize()?;
+        self.touch(parent);
+        self.touch(child);
+        let replaced = match self.content.get_mut(&parent).unwrap() {
             InodeContent::Directory(dir) => dir.insert(
                 name.into_string().or(Err(ParcelError::StringConversion))?,
                 (child, kind),
             ),
             _ => panic!(),
         };
+        self.unlink(replaced);
 
-        self.inodes.get_mut(&child).unwrap().parent = parent;
+        // An inode's attributes count its first entry; any already named is gaining a link
+        let inode = self.inodes.get_mut(&child).ok_or(ParcelError::Enoent)?;
+        if inode.parent != 0 {
+            inode.attrs.nlink += 1;
+        }
+        inode.parent = parent;
         Ok(())
     }
 
     fn insert_whiteout(&mut self, parent: u64, name: OsString) -> Result<()> {
-        match self.content.get_mut(&parent).unwrap() {
+        self.materialize()?;
+        self.touch(parent);
+        let replaced = match self.content.get_mut(&parent).unwrap() {
             InodeContent::Directory(dir) => dir.insert(
                 name.into_string().or(Err(ParcelError::StringConversion))?,
                 (0, InodeKind::Whiteout),
             ),
             _ => panic!(),
         };
+        self.unlink(replaced);
         Ok(())
     }
 
+    /// Drop the link held by a directory entry that was replaced
+    fn unlink(&mut self, entry: Option<(u64, InodeKind)>) {
+        if let Some((ino, _)) = entry.filter(|(ino, _)| self.exists(*ino)) {
+            self.touch(ino);
+            if let Some(inode) = self.inodes.get_mut(&ino) {
+                inode.attrs.nlink = inode.attrs.nlink.saturating_sub(1);
+            }
+        }
+    }
+
     fn select(&self, path: PathBuf) -> Option<u64> {
-        let path = match path.has_root() {
-            true => path,
-            false => Path::new("/").join(path),
-        };
         let mut ino: Option<u64> = None;
-        for ent in path.lexiclean().iter() {
+        for ent in absolute_path(path).iter() {
             if ent == "/" {
                 ino = Some(self.root_inode);
             } else {
-                ino = Some(match self.content.get(&ino?)? {
+                ino = Some(match self.inode_content(ino?)?.as_ref() {
                     InodeContent::Directory(d) => d.get(ent.to_str()?)?.0,
                     _ => return None,
                 });
@@ -618,7 +1862,8 @@ impl Parcel {
             self.on_disk,
             "Parcel is not on disk, cannot read without flushing"
         );
-        let file = match self.content.get(&ino).ok_or(ParcelError::Enoent)? {
+        let content = self.inode_content(ino).ok_or(ParcelError::Enoent)?;
+        let file = match content.as_ref() {
             InodeContent::RegularFile(f) => f,
             _ => return Err(ParcelError::NotFile.into()),
         };
@@ -635,22 +1880,35 @@ impl Parcel {
             Some(s) => max(min(s + offset, file.size) - offset, 0),
             None => file.size,
         };
+        if let Some(segments) = &file.segments {
+            return sparse::read_segments(
+                reader,
+                self.file_offset
+                    .expect("Parcel not properly loaded- no offset stored to data section")
+                    + file.offset,
+                segments,
+                offset,
+                size,
+            );
+        }
         let mut buf = vec![0u8; size as usize];
         reader.read_exact(&mut buf)?;
         Ok(buf)
     }
 
-    fn write<W: Write + Seek>(
+    fn write<W: Read + Write + Seek>(
         &mut self,
         writer: &mut W,
         ino: u64,
         offset: u64,
         buf: &[u8],
     ) -> Result<u64> {
+        self.materialize()?;
         assert!(
             self.on_disk,
             "Parcel is not on disk, cannot write without flushing"
         );
+        self.unshare(writer, ino)?;
         let file = match self.content.get_mut(&ino).ok_or(ParcelError::Enoent)? {
             InodeContent::RegularFile(f) => f,
             _ => return Err(ParcelError::NotFile.into()),
@@ -678,33 +1936,35 @@ impl Parcel {
         offset: u64,
         buf: &[u8],
     ) -> Result<u64> {
-        let file = match self.content.get(&ino).ok_or(ParcelError::Enoent)? {
-            InodeContent::RegularFile(f) => f,
+        let file_size = match self.inode_content(ino).ok_or(ParcelError::Enoent)?.as_ref() {
+            InodeContent::RegularFile(f) => f.size,
             _ => return Err(ParcelError::NotFile.into()),
         };
         let size: u64 = buf.len().try_into()?;
-        if size + offset > file.size {
+        if size + offset > file_size {
             self.realloc_reserved(writer, ino, size + offset)?;
         }
         self.write(writer, ino, offset, buf)
     }
 
     fn exists(&self, ino: u64) -> bool {
-        self.inodes.contains_key(&ino)
+        match &self.lazy {
+            Some(lazy) => lazy.contains(ino),
+            None => self.inodes.contains_key(&ino),
+        }
     }
 
     fn getattr(&self, ino: u64) -> Option<FileAttr> {
-        let inode = self.inodes.get(&ino)?;
+        let (inode, content) = self.inode_entry(ino)?;
         let attrs = inode.attrs;
-        let content = self.content.get(&ino)?;
-        let size = match content {
+        let size = match content.as_ref() {
             InodeContent::RegularFile(f) => f.size,
             InodeContent::Directory(_) => 0,
             InodeContent::Symlink(s) => s.len() as u64,
             InodeContent::Char(_) => 0,
             InodeContent::Whiteout => return None,
         };
-        let kind = match content {
+        let kind = match content.as_ref() {
             InodeContent::RegularFile(_) => InodeKind::RegularFile,
             InodeContent::Directory(_) => InodeKind::Directory,
             InodeContent::Symlink(_) => InodeKind::Symlink,
@@ -731,16 +1991,33 @@ impl Parcel {
     }
 
     fn getattr_mut(&mut self, ino: u64) -> Option<&mut InodeAttr> {
+        self.materialize().ok()?;
+        self.touch(ino);
         let inode = self.inodes.get_mut(&ino)?;
         let attrs = &mut inode.attrs;
         Some(attrs)
     }
 
+    fn set_rdev(&mut self, ino: u64, rdev: u64) -> Result<()> {
+        self.materialize()?;
+        self.touch(ino);
+        self.inodes
+            .get_mut(&ino)
+            .ok_or(ParcelError::Enoent)?
+            .attrs
+            .rdev = rdev;
+        if let Some(InodeContent::Char(content)) = self.content.get_mut(&ino) {
+            *content = rdev;
+        }
+        Ok(())
+    }
+
     fn readdir(&self, ino: u64) -> Option<Vec<(u64, InodeKind, String)>> {
         let mut res: Vec<(u64, InodeKind, String)> = Vec::new();
 
-        let content = match self.content.get(&ino) {
-            Some(InodeContent::Directory(d)) => d,
+        let content = self.inode_content(ino)?;
+        let content = match content.as_ref() {
+            InodeContent::Directory(d) => d,
             _ => return None,
         };
         for (k, (v, kind)) in content.iter() {
@@ -749,8 +2026,35 @@ impl Parcel {
         Some(res)
     }
 
+    fn walk(&self, ino: u64) -> Option<Vec<(u64, InodeKind, PathBuf)>> {
+        let mut res = Vec::new();
+        let mut stack = vec![(ino, PathBuf::new())];
+        while let Some((dir, path)) = stack.pop() {
+            let content = self.inode_content(dir)?;
+            let entries = match content.as_ref() {
+                InodeContent::Directory(d) => d,
+                _ => return None,
+            };
+            let mut subdirs = Vec::new();
+            for (name, (child, kind)) in entries.iter() {
+                if *kind == InodeKind::Whiteout {
+                    continue;
+                }
+                let child_path = path.join(name);
+                res.push((*child, *kind, child_path.clone()));
+                if *kind == InodeKind::Directory {
+                    subdirs.push((*child, child_path));
+                }
+            }
+            // Reversed so the first subdirectory is walked next
+            stack.extend(subdirs.into_iter().rev());
+        }
+        Some(res)
+    }
+
     fn lookup(&self, parent: u64, name: &str) -> Option<u64> {
-        let content = match self.content.get(&parent)? {
+        let content = self.inode_content(parent)?;
+        let content = match content.as_ref() {
             InodeContent::Directory(d) => d,
             _ => return None,
         };
@@ -763,20 +2067,137 @@ impl Parcel {
     }
 
     fn readlink(&self, ino: u64) -> Option<Vec<u8>> {
-        let content = self.content.get(&ino)?;
-        match content {
+        let content = self.inode_content(ino)?;
+        match content.as_ref() {
             InodeContent::Symlink(s) => Some(s.as_bytes().to_vec()),
             _ => panic!(),
         }
     }
 
     fn getxattrs(&self, ino: u64) -> Option<BTreeMap<OsString, Vec<u8>>> {
-        Some(self.inodes.get(&ino)?.xattrs.clone())
+        Some(self.inode_entry(ino)?.0.xattrs.clone())
+    }
+
+    fn mark_config(&mut self, path: PathBuf) -> Result<()> {
+        let path = absolute_path(path);
+        let ino = self.select(path.clone()).ok_or(ParcelError::Enoent)?;
+        if !matches!(
+            self.inode_content(ino).as_deref(),
+            Some(InodeContent::RegularFile(_))
+        ) {
+            return Err(ParcelError::NotFile.into());
+        }
+        self.metadata.config_files.insert(
+            path.into_os_string()
+                .into_string()
+                .or(Err(ParcelError::StringConversion))?,
+        );
+        Ok(())
+    }
+
+    fn is_config(&self, path: PathBuf) -> bool {
+        match absolute_path(path).to_str() {
+            Some(path) => self.metadata.config_files.contains(path),
+            None => false,
+        }
+    }
+
+    fn digest<R: Read + Seek>(&self, reader: &mut R, ino: u64) -> Result<String> {
+        assert!(
+            self.on_disk,
+            "Parcel is not on disk, cannot read without flushing"
+        );
+        let data_offset = self
+            .file_offset
+            .expect("Parcel not properly loaded- no offset stored to data section");
+        let content = self.inode_content(ino).ok_or(ParcelError::Enoent)?;
+        let file = match content.as_ref() {
+            InodeContent::RegularFile(f) => f,
+            _ => return Err(ParcelError::NotFile.into()),
+        };
+        stored_digest(reader, data_offset, file)
+    }
+
+    fn segments(&self, ino: u64) -> Option<Vec<Segment>> {
+        match self.inode_content(ino)?.as_ref() {
+            InodeContent::RegularFile(f) => f.segments.clone(),
+            _ => None,
+        }
+    }
+
+    fn config_digests<R: Read + Seek>(&self, reader: &mut R) -> Result<BTreeMap<String, String>> {
+        let mut res = BTreeMap::new();
+        for path in self.metadata.config_files.iter() {
+            let ino = self
+                .select(PathBuf::from(path))
+                .ok_or(ParcelError::Enoent)?;
+            res.insert(path.clone(), self.digest(reader, ino)?);
+        }
+        Ok(res)
+    }
+
+    fn sign<R: Read + Seek>(&mut self, reader: &mut R, key: &SigningKey) -> Result<()> {
+        self.materialize()?;
+        assert!(
+            self.on_disk,
+            "Parcel is not on disk, cannot sign without flushing"
+        );
+        let data_offset = self
+            .file_offset
+            .expect("Parcel not properly loaded- no offset stored to data section");
+        for ino in self.inos() {
+            self.touch(ino);
+        }
+        for content in self.content.values_mut() {
+            if let InodeContent::RegularFile(file) = content {
+                file.digest = Some(stored_digest(reader, data_offset, file)?);
+            }
+        }
+        self.signature = Some(signing::sign(&self.signed_bytes()?, key));
+        Ok(())
+    }
+
+    fn verify_signature<R: Read + Seek>(&self, reader: &mut R, key: &VerifyingKey) -> Result<()> {
+        assert!(
+            self.on_disk,
+            "Parcel is not on disk, cannot verify without flushing"
+        );
+        signing::verify(
+            &self.signed_bytes()?,
+            self.signature.as_ref().ok_or(ParcelError::Unsigned)?,
+            key,
+        )?;
+        let data_offset = self
+            .file_offset
+            .expect("Parcel not properly loaded- no offset stored to data section");
+        for ino in self.inos() {
+            if let Some(InodeContent::RegularFile(file)) = self.inode_content(ino).as_deref() {
+                let digest = stored_digest(reader, data_offset, file)?;
+                if file.digest.as_ref() != Some(&digest) {
+                    return Err(ParcelError::DigestMismatch { ino }.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn getxattrs_mut(&mut self, ino: u64) -> Option<&mut BTreeMap<OsString, Vec<u8>>> {
+        self.materialize().ok()?;
+        self.touch(ino);
+        Some(&mut self.inodes.get_mut(&ino)?.xattrs)
     }
 
     fn delete(&mut self, ino: u64) -> Result<()> {
+        self.materialize()?;
+        self.touch(ino);
         self.inodes.remove(&ino).ok_or(ParcelError::Enoent)?;
-        self.content.remove(&ino).ok_or(ParcelError::Enoent)?;
+        if let InodeContent::RegularFile(f) =
+            self.content.remove(&ino).ok_or(ParcelError::Enoent)?
+        {
+            if f.capacity > 0 {
+                self.release(f.offset);
+            }
+        }
         Ok(())
     }
 }
@@ -0,0 +1,132 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+    os::unix::fs::FileExt,
+};
+
+use crate::parcel::FileBacking;
+
+/// Reads smaller than this are rounded up to it and buffered, so that reading a header a line
+/// at a time doesn't become a request per line
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// A read-only source of bytes that can be read at any offset, such as a local file or a file
+/// on a web server
+pub trait RandomAccess {
+    /// Total size of the source in bytes
+    fn size(&self) -> io::Result<u64>;
+    /// Read into `buf` from `offset`, returning the number of bytes read, which is zero only at
+    /// the end of the source
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+}
+
+impl RandomAccess for File {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        FileExt::read_at(self, buf, offset)
+    }
+}
+
+/// Adapter to load parcels from a random-access source. Writes fail, so the parcel can be read
+/// but not stored.
+pub struct RandomAccessBacking<R: RandomAccess> {
+    source: R,
+    size:   u64,
+    pos:    u64,
+    /// Bytes of the source starting at `start`
+    buf:    Vec<u8>,
+    start:  u64,
+}
+
+impl<R: RandomAccess> RandomAccessBacking<R> {
+    /// Create a backing reading from `source`
+    pub fn new(source: R) -> io::Result<Self> {
+        Ok(Self {
+            size: source.size()?,
+            source,
+            pos: 0,
+            buf: Vec::new(),
+            start: 0,
+        })
+    }
+
+    /// The source being read
+    pub fn source(&self) -> &R {
+        &self.source
+    }
+
+    fn buffered(&self) -> &[u8] {
+        match self.pos.checked_sub(self.start) {
+            Some(skip) if skip < self.buf.len() as u64 => &self.buf[skip as usize..],
+            _ => &[],
+        }
+    }
+}
+
+impl<R: RandomAccess> FileBacking for RandomAccessBacking<R> {
+    fn writable(&self) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+impl<R: RandomAccess> Seek for RandomAccessBacking<R> {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        let pos = match from {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.size.checked_add_signed(delta),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Seek to a negative position")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+impl<R: RandomAccess> Read for RandomAccessBacking<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        // Large reads go straight to the source rather than through the buffer
+        if self.buffered().is_empty() && out.len() >= BUFFER_SIZE {
+            let read = self.source.read_at(out, self.pos)?;
+            self.pos += read as u64;
+            return Ok(read);
+        }
+        let available = self.fill_buf()?;
+        let read = available.len().min(out.len());
+        out[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<R: RandomAccess> BufRead for RandomAccessBacking<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.buffered().is_empty() && self.pos < self.size {
+            let len = (self.size - self.pos).min(BUFFER_SIZE as u64) as usize;
+            self.buf.resize(len, 0);
+            let read = self.source.read_at(&mut self.buf, self.pos)?;
+            self.buf.truncate(read);
+            self.start = self.pos;
+        }
+        Ok(self.buffered())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt.min(self.buffered().len()) as u64;
+    }
+}
+
+impl<R: RandomAccess> Write for RandomAccessBacking<R> {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "Random-access backings are read-only",
+        ))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -37,7 +37,11 @@ impl ReaderWriter {
     }
 }
 
-impl parcel::FileBacking for ReaderWriter {}
+impl parcel::FileBacking for ReaderWriter {
+    fn file(&self) -> Option<&File> {
+        Some(&self.file)
+    }
+}
 
 impl Seek for ReaderWriter {
     fn seek(&mut self, from: SeekFrom) -> Result<u64, io::Error> {
@@ -0,0 +1,62 @@
+use std::{
+    cmp::min,
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use anyhow::Result;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::error::ParcelError;
+
+/// Hash `size` bytes of the backing starting at `offset`
+pub fn file_digest<R: Read + Seek>(reader: &mut R, offset: u64, size: u64) -> Result<String> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut hasher = Sha256::new();
+    let copied = io::copy(&mut reader.take(size), &mut hasher)?;
+    if copied != size {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Copy `size` bytes from `reader` to `writer`, returning the digest of what was copied
+pub fn copy_digest<R: Read, W: Write + ?Sized>(
+    reader: &mut R,
+    writer: &mut W,
+    size: u64,
+) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 << 10];
+    let mut left = size;
+    while left > 0 {
+        let want = min(left, buf.len() as u64) as usize;
+        let len = reader.read(&mut buf[..want])?;
+        if len == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        hasher.update(&buf[..len]);
+        writer.write_all(&buf[..len])?;
+        left -= len as u64;
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Hash an in-memory buffer
+pub fn bytes_digest(buf: &[u8]) -> String {
+    hex::encode(Sha256::digest(buf))
+}
+
+/// Produce a hex-encoded signature over the header
+pub fn sign(header: &[u8], key: &SigningKey) -> String {
+    hex::encode(key.sign(header).to_bytes())
+}
+
+/// Check a hex-encoded signature over the header
+pub fn verify(header: &[u8], signature: &str, key: &VerifyingKey) -> Result<()> {
+    let bytes = hex::decode(signature).or(Err(ParcelError::BadSignature))?;
+    let signature = Signature::from_slice(&bytes).or(Err(ParcelError::BadSignature))?;
+    key.verify(header, &signature)
+        .or(Err(ParcelError::BadSignature))?;
+    Ok(())
+}
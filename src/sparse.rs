@@ -0,0 +1,110 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
+};
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+/// A run of data within a sparse file: (logical offset, length). Everything else is a hole.
+pub type Segment = (u64, u64);
+
+/// Find the data segments of a file with holes, or `None` if it has no holes
+pub fn data_segments(file: &File, size: u64) -> Result<Option<Vec<Segment>>> {
+    let fd = file.as_raw_fd();
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    while pos < size {
+        let start = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_DATA) };
+        if start < 0 {
+            match io::Error::last_os_error().raw_os_error() {
+                // No data past `pos`: the rest of the file is a hole
+                Some(libc::ENXIO) => break,
+                // Filesystems without hole support: treat the file as dense
+                Some(libc::EINVAL) => return Ok(None),
+                _ => return Err(io::Error::last_os_error().into()),
+            }
+        }
+        let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+        if end < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let (start, end) = (start as u64, (end as u64).min(size));
+        segments.push((start, end - start));
+        pos = end;
+    }
+    Ok(match segments.as_slice() {
+        [(0, len)] if *len == size => None,
+        _ => Some(segments),
+    })
+}
+
+/// Number of bytes the data segments occupy once packed together
+pub fn stored_len(segments: &[Segment]) -> u64 {
+    segments.iter().map(|(_, len)| len).sum()
+}
+
+/// Copy the data segments of `source` to `writer`, packed together
+pub fn copy_segments<W: Write + ?Sized>(
+    source: &mut File,
+    segments: &[Segment],
+    writer: &mut W,
+) -> Result<u64> {
+    let mut copied = 0;
+    for (start, len) in segments {
+        source.seek(SeekFrom::Start(*start))?;
+        copied += io::copy(&mut Read::by_ref(source).take(*len), writer)?;
+    }
+    Ok(copied)
+}
+
+/// Read `len` bytes from logical `offset` of a sparse file whose packed data starts at `base`
+pub fn read_segments<R: Read + Seek>(
+    reader: &mut R,
+    base: u64,
+    segments: &[Segment],
+    offset: u64,
+    len: u64,
+) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; len as usize];
+    let mut stored = base;
+    for (start, seg_len) in segments {
+        let from = offset.max(*start);
+        let to = (offset + len).min(start + seg_len);
+        if from < to {
+            reader.seek(SeekFrom::Start(stored + from - start))?;
+            reader.read_exact(&mut buf[(from - offset) as usize..(to - offset) as usize])?;
+        }
+        stored += seg_len;
+    }
+    Ok(buf)
+}
+
+/// Hash the logical contents of a sparse file, holes reading as zeros
+pub fn digest<R: Read + Seek>(
+    reader: &mut R,
+    base: u64,
+    segments: &[Segment],
+    size: u64,
+) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let zeros = [0u8; 8192];
+    let mut pos = 0;
+    let mut stored = base;
+    for (start, len) in segments.iter().chain([(size, 0)].iter()) {
+        while pos < *start {
+            let n = (start - pos).min(zeros.len() as u64);
+            hasher.update(&zeros[..n as usize]);
+            pos += n;
+        }
+        reader.seek(SeekFrom::Start(stored))?;
+        let copied = io::copy(&mut reader.by_ref().take(*len), &mut hasher)?;
+        if copied != *len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        pos += len;
+        stored += len;
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
@@ -0,0 +1,513 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{
+    header::{self, Layout, Record},
+    inode::{Inode, InodeContent, InodeKind},
+    migrate, sparse, PARCEL_VERSION,
+};
+
+/// Ends the header, in every format version
+const TERMINATOR: &[u8] = b"\n...\n";
+
+/// A way in which a parcel breaks the format specification. The invariants are:
+///
+/// - The file starts with the magic `413\n`.
+/// - Since version 4, the magic is followed by a layout line giving the position of the data
+///   section and of the header slot, which lies within the data section and the file. Earlier
+///   versions put the header straight after the magic.
+/// - The header is a YAML document naming a version this crate can read, and is followed by the
+///   terminator `\n...\n`.
+/// - The header's index lists every record once, in ascending inode order, at the position and
+///   length the record actually has.
+/// - The root inode exists and is a directory.
+/// - Each inode's contents are of the kind the inode records.
+/// - Every directory entry names an existing inode of the kind the entry records, apart from
+///   whiteouts, which name no inode.
+/// - Every inode can be reached from the root. Its parent is a directory with an entry naming
+///   it, and its link count is the number of such entries, counting the root as linked once.
+/// - A file's data fits within its capacity, and its extent within the data section. Extents
+///   overlap neither each other nor the header slot, except that identical files may share one.
+/// - The data runs of a file with holes are in order, apart, and within the file's size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The file doesn't start with the magic
+    BadMagic,
+    /// The layout line is malformed or points outside the file
+    BadLayout,
+    /// The header isn't followed by the terminator
+    Unterminated,
+    /// The header names a version that can't be read, or that doesn't match its layout
+    UnsupportedVersion(u32),
+    /// The header isn't valid YAML of the expected shape
+    MalformedHeader(String),
+    /// An index entry doesn't match the record it should describe
+    BadIndex {
+        /// Position of the entry within the index
+        entry: usize,
+    },
+    /// The root inode is missing or isn't a directory
+    BadRoot(u64),
+    /// An inode's contents are of a different kind than the inode
+    KindMismatch {
+        /// Inode with the mismatched contents
+        ino: u64,
+    },
+    /// A directory entry names an inode that doesn't exist
+    DanglingDirent {
+        /// Directory holding the entry
+        dir:  u64,
+        /// Name of the entry
+        name: String,
+        /// Inode the entry names
+        ino:  u64,
+    },
+    /// A directory entry records a different kind than the inode it names
+    DirentKind {
+        /// Directory holding the entry
+        dir:      u64,
+        /// Name of the entry
+        name:     String,
+        /// Kind recorded in the entry
+        recorded: InodeKind,
+        /// Kind of the inode
+        actual:   InodeKind,
+    },
+    /// An inode can't be reached from the root
+    Orphan {
+        /// The unreachable inode
+        ino: u64,
+    },
+    /// An inode's parent has no entry naming it
+    WrongParent {
+        /// The inode
+        ino:      u64,
+        /// Parent recorded in the inode
+        recorded: u64,
+        /// A directory with an entry naming the inode
+        expected: u64,
+    },
+    /// An inode's link count differs from the number of entries naming it
+    WrongNlink {
+        /// The inode
+        ino:      u64,
+        /// Link count recorded in the inode
+        recorded: u32,
+        /// Number of entries naming the inode
+        expected: u32,
+    },
+    /// A file holds more data than the space reserved for it
+    SizeExceedsCapacity {
+        /// The file
+        ino: u64,
+    },
+    /// A file's extent runs past the end of the data section
+    OutOfBounds {
+        /// The file
+        ino: u64,
+    },
+    /// Two files' extents overlap without being the same extent
+    ExtentOverlap {
+        /// The file whose extent starts first
+        ino:   u64,
+        /// The file whose extent starts inside it
+        other: u64,
+    },
+    /// A file's extent overlaps the header slot
+    HeaderOverlap {
+        /// The file
+        ino: u64,
+    },
+    /// The data runs of a file with holes are out of order, overlap, or exceed its size
+    BadSegments {
+        /// The file
+        ino: u64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::BadMagic => write!(f, "bad magic"),
+            Violation::BadLayout => write!(f, "bad layout line"),
+            Violation::Unterminated => write!(f, "header is not terminated"),
+            Violation::UnsupportedVersion(version) => {
+                write!(f, "unsupported version {}", version)
+            }
+            Violation::MalformedHeader(reason) => write!(f, "malformed header: {}", reason),
+            Violation::BadIndex { entry } => write!(f, "index entry {} is wrong", entry),
+            Violation::BadRoot(ino) => write!(f, "root inode {} is not a directory", ino),
+            Violation::KindMismatch { ino } => {
+                write!(f, "inode {}: contents don't match its kind", ino)
+            }
+            Violation::DanglingDirent { dir, name, ino } => write!(
+                f,
+                "inode {}: entry {} names missing inode {}",
+                dir, name, ino
+            ),
+            Violation::DirentKind {
+                dir,
+                name,
+                recorded,
+                actual,
+            } => write!(
+                f,
+                "inode {}: entry {} recorded as {:?}, is {:?}",
+                dir, name, recorded, actual
+            ),
+            Violation::Orphan { ino } => write!(f, "inode {}: unreachable", ino),
+            Violation::WrongParent {
+                ino,
+                recorded,
+                expected,
+            } => write!(
+                f,
+                "inode {}: parent is {}, should be {}",
+                ino, recorded, expected
+            ),
+            Violation::WrongNlink {
+                ino,
+                recorded,
+                expected,
+            } => write!(
+                f,
+                "inode {}: link count is {}, should be {}",
+                ino, recorded, expected
+            ),
+            Violation::SizeExceedsCapacity { ino } => {
+                write!(f, "inode {}: size exceeds capacity", ino)
+            }
+            Violation::OutOfBounds { ino } => {
+                write!(f, "inode {}: extent past the data section", ino)
+            }
+            Violation::ExtentOverlap { ino, other } => {
+                write!(f, "inode {}: extent overlaps inode {}", ino, other)
+            }
+            Violation::HeaderOverlap { ino } => {
+                write!(f, "inode {}: extent overlaps the header", ino)
+            }
+            Violation::BadSegments { ino } => write!(f, "inode {}: bad data segments", ino),
+        }
+    }
+}
+
+/// Just enough of a header to learn its version
+#[derive(Deserialize)]
+struct Versioned {
+    version: u32,
+}
+
+/// A whole current header, decoded at once rather than through its index
+#[derive(Deserialize)]
+struct FullHeader {
+    root_inode: u64,
+    index:      String,
+    records:    Vec<Record<'static>>,
+}
+
+/// Check a stored parcel against the format specification, without loading it. Parcels written
+/// by earlier versions are checked as they would be upgraded. Only failures to read the input
+/// are errors; everything wrong with the parcel itself is reported as a [`Violation`].
+pub fn validate<R: Read + Seek>(input: &mut R) -> Result<Vec<Violation>> {
+    let file_len = input.seek(SeekFrom::End(0))?;
+    input.seek(SeekFrom::Start(0))?;
+    let mut input = BufReader::new(input);
+
+    let mut magic = [0u8; 4];
+    if input.read_exact(&mut magic).is_err() || &magic != b"413\n" {
+        return Ok(vec![Violation::BadMagic]);
+    }
+
+    // Read the header, and find the data section and the header's slot within it
+    let layout = input.fill_buf()?.first() == Some(&b'@');
+    let (mut buf, data, slot) = match layout {
+        true => {
+            let mut line = [0u8; Layout::LEN as usize];
+            let layout = match input.read_exact(&mut line) {
+                Ok(()) => Layout::parse(&line).ok(),
+                Err(_) => None,
+            };
+            let layout = match layout {
+                Some(l)
+                    if l.data >= 4 + Layout::LEN
+                        && l.header >= l.data
+                        && l.len + TERMINATOR.len() as u64 <= l.capacity
+                        && l.header
+                            .checked_add(l.capacity)
+                            .is_some_and(|e| e <= file_len) =>
+                {
+                    l
+                }
+                _ => return Ok(vec![Violation::BadLayout]),
+            };
+            input.seek(SeekFrom::Start(layout.header))?;
+            let mut buf = vec![0u8; (layout.len + TERMINATOR.len() as u64) as usize];
+            input.read_exact(&mut buf)?;
+            if !buf.ends_with(TERMINATOR) {
+                return Ok(vec![Violation::Unterminated]);
+            }
+            buf.truncate(layout.len as usize);
+            let slot = (layout.header - layout.data, layout.capacity);
+            (buf, layout.data, Some(slot))
+        }
+        false => {
+            let mut buf = Vec::new();
+            loop {
+                if input.read_until(b'\n', &mut buf)? == 0 {
+                    return Ok(vec![Violation::Unterminated]);
+                }
+                if buf.ends_with(TERMINATOR) {
+                    break;
+                }
+            }
+            let data = 4 + buf.len() as u64;
+            buf.truncate(buf.len() - TERMINATOR.len());
+            (buf, data, None)
+        }
+    };
+
+    let version = match serde_yaml::from_slice::<Versioned>(header::summary_bytes(&buf)) {
+        Ok(v) => v.version,
+        Err(e) => return Ok(vec![Violation::MalformedHeader(e.to_string())]),
+    };
+    let supported = match layout {
+        true => version == PARCEL_VERSION,
+        false => (migrate::OLDEST_VERSION..PARCEL_VERSION).contains(&version),
+    };
+    if !supported {
+        return Ok(vec![Violation::UnsupportedVersion(version)]);
+    }
+    if version != PARCEL_VERSION {
+        buf = match migrate::upgrade(&buf, version) {
+            Ok(buf) => buf,
+            Err(e) => return Ok(vec![Violation::MalformedHeader(e.to_string())]),
+        };
+    }
+
+    let full: FullHeader = match serde_yaml::from_slice(&buf) {
+        Ok(full) => full,
+        Err(e) => return Ok(vec![Violation::MalformedHeader(e.to_string())]),
+    };
+    let mut violations = check_index(&buf, &full);
+    let mut inodes = BTreeMap::new();
+    let mut content = BTreeMap::new();
+    for record in full.records {
+        inodes.insert(record.ino, record.inode.into_owned());
+        content.insert(record.ino, record.content.into_owned());
+    }
+    violations.extend(check_tree(
+        full.root_inode,
+        &inodes,
+        &content,
+        Some(file_len - data.min(file_len)),
+        slot,
+    ));
+    Ok(violations)
+}
+
+/// Check that the index describes the records exactly as they are laid out
+fn check_index(buf: &[u8], full: &FullHeader) -> Vec<Violation> {
+    let key = b"\nrecords:\n";
+    let mut block = match buf.windows(key.len()).position(|w| w == key) {
+        Some(pos) => &buf[pos + key.len()..],
+        None => return vec![Violation::BadIndex { entry: 0 }],
+    };
+    // Version 3 headers were padded with spaces to the space reserved for them
+    while let Some(rest) = block.strip_suffix(b" ") {
+        block = rest;
+    }
+    let mut violations = Vec::new();
+    let mut expected = 0;
+    let lines: Vec<&str> = full.index.lines().collect();
+    for (entry, line) in lines.iter().enumerate() {
+        let fields: Option<Vec<u64>> = line
+            .split(' ')
+            .map(|field| u64::from_str_radix(field, 16).ok())
+            .collect();
+        let good = match (fields.as_deref(), full.records.get(entry)) {
+            (Some(&[ino, offset, len]), Some(record)) => {
+                let start = offset as usize;
+                let end = start + len as usize;
+                ino == record.ino
+                    && offset == expected
+                    && block
+                        .get(start..end)
+                        .is_some_and(|r| r.starts_with(b"  - "))
+            }
+            _ => false,
+        };
+        if !good {
+            violations.push(Violation::BadIndex { entry });
+        }
+        if let Some(&[_, offset, len]) = fields.as_deref() {
+            expected = offset + len;
+        }
+    }
+    if lines.len() != full.records.len() || expected != block.len() as u64 {
+        violations.push(Violation::BadIndex { entry: lines.len() });
+    }
+    violations
+}
+
+fn content_kind(content: &InodeContent) -> InodeKind {
+    match content {
+        InodeContent::Directory(_) => InodeKind::Directory,
+        InodeContent::RegularFile(_) => InodeKind::RegularFile,
+        InodeContent::Symlink(_) => InodeKind::Symlink,
+        InodeContent::Char(_) => InodeKind::CharDevice,
+        InodeContent::Whiteout => InodeKind::Whiteout,
+    }
+}
+
+/// Check the inodes of a parcel against each other and against the data section, which holds
+/// the header in `slot`, if given. Extents are only checked against the end of the data
+/// section if its length is known.
+pub(crate) fn check_tree(
+    root_inode: u64,
+    inodes: &BTreeMap<u64, Inode>,
+    content: &BTreeMap<u64, InodeContent>,
+    data_len: Option<u64>,
+    slot: Option<(u64, u64)>,
+) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    if inodes.get(&root_inode).map(|i| i.kind) != Some(InodeKind::Directory) {
+        violations.push(Violation::BadRoot(root_inode));
+    }
+
+    // Directories with an entry naming each inode, once per entry
+    let mut named_by: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+    named_by.insert(root_inode, vec![0]);
+
+    // (offset, capacity, owning inode), with the header owned by nothing
+    let mut extents: Vec<(u64, u64, Option<u64>)> = slot
+        .into_iter()
+        .map(|(offset, capacity)| (offset, capacity, None))
+        .collect();
+    for (ino, inode) in inodes {
+        let content = &content[ino];
+        if content_kind(content) != inode.kind {
+            violations.push(Violation::KindMismatch { ino: *ino });
+        }
+        match content {
+            InodeContent::Directory(entries) => {
+                for (name, (target, recorded)) in entries {
+                    if *recorded == InodeKind::Whiteout && *target == 0 {
+                        continue;
+                    }
+                    match inodes.get(target) {
+                        None => violations.push(Violation::DanglingDirent {
+                            dir:  *ino,
+                            name: name.clone(),
+                            ino:  *target,
+                        }),
+                        Some(t) if t.kind != *recorded => violations.push(Violation::DirentKind {
+                            dir:      *ino,
+                            name:     name.clone(),
+                            recorded: *recorded,
+                            actual:   t.kind,
+                        }),
+                        Some(_) => (),
+                    }
+                    if inodes.contains_key(target) {
+                        named_by.entry(*target).or_default().push(*ino);
+                    }
+                }
+            }
+            InodeContent::RegularFile(f) => {
+                let stored = match &f.segments {
+                    Some(segments) => {
+                        let mut end = 0;
+                        for (offset, len) in segments {
+                            if *offset < end || offset + len > f.size {
+                                violations.push(Violation::BadSegments { ino: *ino });
+                                break;
+                            }
+                            end = offset + len;
+                        }
+                        sparse::stored_len(segments)
+                    }
+                    None => f.size,
+                };
+                if stored > f.capacity {
+                    violations.push(Violation::SizeExceedsCapacity { ino: *ino });
+                }
+                if f.offset
+                    .checked_add(f.capacity)
+                    .is_none_or(|e| data_len.is_some_and(|len| e > len))
+                {
+                    violations.push(Violation::OutOfBounds { ino: *ino });
+                }
+                if f.capacity > 0 {
+                    extents.push((f.offset, f.capacity, Some(*ino)));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Follow entries down from the root to find what is reachable
+    let mut reachable = BTreeSet::new();
+    let mut stack = vec![root_inode];
+    while let Some(ino) = stack.pop() {
+        if !reachable.insert(ino) {
+            continue;
+        }
+        if let Some(InodeContent::Directory(entries)) = content.get(&ino) {
+            stack.extend(
+                entries
+                    .values()
+                    .map(|(target, _)| *target)
+                    .filter(|target| inodes.contains_key(target)),
+            );
+        }
+    }
+    for (ino, inode) in inodes {
+        if !reachable.contains(ino) {
+            violations.push(Violation::Orphan { ino: *ino });
+            continue;
+        }
+        let parents = &named_by[ino];
+        if *ino != root_inode && !parents.contains(&inode.parent) {
+            violations.push(Violation::WrongParent {
+                ino:      *ino,
+                recorded: inode.parent,
+                expected: parents[0],
+            });
+        }
+        if inode.attrs.nlink as usize != parents.len() {
+            violations.push(Violation::WrongNlink {
+                ino:      *ino,
+                recorded: inode.attrs.nlink,
+                expected: parents.len() as u32,
+            });
+        }
+    }
+
+    // Files sharing an extent start at the same offset; anything else starting inside an
+    // extent overlaps it
+    extents.sort();
+    let mut last: Option<(u64, u64, Option<u64>)> = None;
+    for (offset, capacity, owner) in extents {
+        if let Some((start, end, first)) = last {
+            let shared = offset == start && first.is_some() && owner.is_some();
+            if !shared && offset < end {
+                violations.push(match (first, owner) {
+                    (Some(ino), Some(other)) => Violation::ExtentOverlap { ino, other },
+                    (Some(ino), None) | (None, Some(ino)) => Violation::HeaderOverlap { ino },
+                    (None, None) => unreachable!("Parcel has one header slot"),
+                });
+            }
+            if offset + capacity <= end {
+                continue;
+            }
+        }
+        last = Some((offset, offset.saturating_add(capacity), owner));
+    }
+    violations
+}
@@ -0,0 +1,283 @@
+use std::{
+    cmp::min,
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    io::{self, BufReader, Read, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use lexiclean::Lexiclean;
+use tar::{Archive, Builder, EntryType, Header};
+
+use crate::{error::ParcelError, InodeAttr, InodeKind, ParcelHandle};
+
+/// PAX key prefix used by GNU tar and bsdtar for extended attributes
+const PAX_XATTR_PREFIX: &str = "SCHILY.xattr.";
+
+/// Parse a PAX timestamp (decimal seconds, optionally fractional)
+fn pax_time(value: &str) -> Option<SystemTime> {
+    let (secs, frac) = value.split_once('.').unwrap_or((value, ""));
+    let secs: u64 = secs.parse().ok()?;
+    let nanos: u32 = match frac.len() {
+        0 => 0,
+        len => format!("{:0<9}", &frac[..len.min(9)]).parse().ok()?,
+    };
+    Some(UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+/// Format a timestamp as PAX decimal seconds
+fn pax_timestamp(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:09}", since.as_secs(), since.subsec_nanos())
+}
+
+/// Root a tar member path at `/` and normalize it
+fn member_path(path: &Path) -> PathBuf {
+    Path::new("/").join(path).lexiclean()
+}
+
+/// Reads a file's contents from a parcel a piece at a time
+struct FileReader<'a> {
+    handle: &'a mut ParcelHandle,
+    ino:    u64,
+    offset: u64,
+    size:   u64,
+}
+
+impl Read for FileReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let want = min(buf.len() as u64, self.size - self.offset);
+        if want == 0 {
+            return Ok(0);
+        }
+        let data = self
+            .handle
+            .read(self.ino, self.offset, Some(want))
+            .map_err(io::Error::other)?;
+        if data.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf[..data.len()].copy_from_slice(&data);
+        self.offset += data.len() as u64;
+        Ok(data.len())
+    }
+}
+
+impl ParcelHandle {
+    /// Add every entry of an uncompressed tar stream to the parcel.
+    /// Missing parent directories are created with default attributes.
+    pub fn import_tar<R: Read>(&mut self, reader: R) -> Result<()> {
+        let mut archive = Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let header = entry.header();
+            let kind = header.entry_type();
+            let path = member_path(&entry.path()?);
+            let link_name = entry.link_name()?.map(|l| l.into_owned());
+
+            let mtime = UNIX_EPOCH + Duration::from_secs(header.mtime()?);
+            let mut attrs = InodeAttr {
+                atime: mtime,
+                mtime,
+                ctime: mtime,
+                perm: header.mode()? & 0o7777,
+                nlink: 1,
+                uid: header.uid()? as u32,
+                gid: header.gid()? as u32,
+                rdev: 0,
+            };
+            attrs.perm |= match kind {
+                EntryType::Directory => libc::S_IFDIR,
+                EntryType::Symlink => libc::S_IFLNK,
+                EntryType::Char => libc::S_IFCHR,
+                _ => libc::S_IFREG,
+            };
+            if kind == EntryType::Char {
+                attrs.rdev = libc::makedev(
+                    header.device_major()?.unwrap_or(0),
+                    header.device_minor()?.unwrap_or(0),
+                );
+            }
+
+            let mut xattrs: BTreeMap<OsString, Vec<u8>> = BTreeMap::new();
+            if let Some(extensions) = entry.pax_extensions()? {
+                for ext in extensions {
+                    let ext = ext?;
+                    let key = ext.key().or(Err(ParcelError::StringConversion))?;
+                    let value = ext.value();
+                    match key {
+                        "mtime" => attrs.mtime = value.ok().and_then(pax_time).unwrap_or(mtime),
+                        "atime" => attrs.atime = value.ok().and_then(pax_time).unwrap_or(mtime),
+                        "ctime" => attrs.ctime = value.ok().and_then(pax_time).unwrap_or(mtime),
+                        _ => {
+                            if let Some(name) = key.strip_prefix(PAX_XATTR_PREFIX) {
+                                xattrs.insert(name.into(), ext.value_bytes().to_vec());
+                            }
+                        }
+                    }
+                }
+            }
+
+            if path == Path::new("/") {
+                if kind == EntryType::Directory {
+                    *self.getattr_mut(1).ok_or(ParcelError::Enoent)? = attrs;
+                    *self.getxattrs_mut(1).ok_or(ParcelError::Enoent)? = xattrs;
+                }
+                continue;
+            }
+            let parent = self.ensure_dir(path.parent().unwrap_or_else(|| Path::new("/")))?;
+            let name = path.file_name().ok_or(ParcelError::Enoent)?.to_os_string();
+
+            let (ino, inode_kind) = match kind {
+                EntryType::Regular | EntryType::Continuous => {
+                    let size = entry.size();
+                    (
+                        self.add_file_reader(&mut entry, size, attrs, xattrs)?,
+                        InodeKind::RegularFile,
+                    )
+                }
+                EntryType::Directory => match self.lookup(parent, &name.to_string_lossy()) {
+                    // Created implicitly for an earlier member; now we know its real attributes
+                    Some(ino) => {
+                        let existing = self.getattr_mut(ino).ok_or(ParcelError::Enoent)?;
+                        *existing = InodeAttr {
+                            nlink: existing.nlink,
+                            ..attrs
+                        };
+                        *self.getxattrs_mut(ino).ok_or(ParcelError::Enoent)? = xattrs;
+                        continue;
+                    }
+                    None => (self.add_directory(attrs, xattrs)?, InodeKind::Directory),
+                },
+                EntryType::Symlink => (
+                    self.add_symlink(
+                        link_name.ok_or(ParcelError::Enoent)?.into_os_string(),
+                        attrs,
+                        xattrs,
+                    )?,
+                    InodeKind::Symlink,
+                ),
+                EntryType::Link => {
+                    let target = member_path(&link_name.ok_or(ParcelError::Enoent)?);
+                    let ino = self.add_hardlink(target.into_os_string())?;
+                    let inode_kind = self.getattr(ino).ok_or(ParcelError::Enoent)?.kind;
+                    (ino, inode_kind)
+                }
+                EntryType::Char => (self.add_char(attrs, xattrs)?, InodeKind::CharDevice),
+                EntryType::XGlobalHeader | EntryType::XHeader => continue,
+                other => {
+                    return Err(ParcelError::UnsupportedType(format!(
+                        "{:?} ({})",
+                        other,
+                        path.display()
+                    ))
+                    .into())
+                }
+            };
+            self.insert_dirent(parent, name, ino, inode_kind)?;
+        }
+        Ok(())
+    }
+
+    /// Look up a directory by path, creating it and any missing ancestors
+    pub(crate) fn ensure_dir(&mut self, path: &Path) -> Result<u64> {
+        if let Some(ino) = self.select(path.to_path_buf()) {
+            return Ok(ino);
+        }
+        let parent = self.ensure_dir(path.parent().unwrap_or_else(|| Path::new("/")))?;
+        let attrs = InodeAttr {
+            perm: libc::S_IFDIR | 0o755,
+            ..Default::default()
+        };
+        let ino = self.add_directory(attrs, BTreeMap::new())?;
+        let name = path.file_name().ok_or(ParcelError::Enoent)?;
+        self.insert_dirent(parent, name.to_os_string(), ino, InodeKind::Directory)?;
+        Ok(ino)
+    }
+
+    /// Write the subtree rooted at `path` to a POSIX (PAX) tar stream.
+    /// Member names are relative to `path`; xattrs and precise timestamps go in PAX records.
+    pub fn export_tar<W: Write>(&mut self, path: PathBuf, writer: W) -> Result<()> {
+        let top = self.select(path).ok_or(ParcelError::Enoent)?;
+        let mut builder = Builder::new(writer);
+        let mut written: BTreeMap<u64, PathBuf> = BTreeMap::new();
+        for (ino, kind, path) in self.walk(top).ok_or(ParcelError::NotDirectory)? {
+            let attr = self.getattr(ino).ok_or(ParcelError::Enoent)?;
+            let mut header = Header::new_ustar();
+            header.set_mode(attr.perm as u32 & 0o7777);
+            header.set_uid(attr.uid as u64);
+            header.set_gid(attr.gid as u64);
+            header.set_mtime(
+                attr.mtime
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            );
+            header.set_size(0);
+
+            let mut pax: Vec<(String, Vec<u8>)> = vec![
+                ("mtime".into(), pax_timestamp(attr.mtime).into_bytes()),
+                ("atime".into(), pax_timestamp(attr.atime).into_bytes()),
+                ("ctime".into(), pax_timestamp(attr.ctime).into_bytes()),
+            ];
+            for (name, value) in self.getxattrs(ino).unwrap_or_default() {
+                pax.push((
+                    format!("{}{}", PAX_XATTR_PREFIX, name.to_string_lossy()),
+                    value,
+                ));
+            }
+            builder.append_pax_extensions(pax.iter().map(|(k, v)| (k.as_str(), v.as_slice())))?;
+
+            if let Some(first) = written.get(&ino) {
+                header.set_entry_type(EntryType::Link);
+                builder.append_link(&mut header, &path, first)?;
+                continue;
+            }
+            match kind {
+                InodeKind::Directory => {
+                    header.set_entry_type(EntryType::Directory);
+                    builder.append_data(&mut header, &path, &[][..])?;
+                }
+                InodeKind::RegularFile => {
+                    header.set_entry_type(EntryType::Regular);
+                    header.set_size(attr.size);
+                    let contents = FileReader {
+                        handle: self,
+                        ino,
+                        offset: 0,
+                        size: attr.size,
+                    };
+                    builder.append_data(
+                        &mut header,
+                        &path,
+                        BufReader::with_capacity(64 << 10, contents),
+                    )?;
+                }
+                InodeKind::Symlink => {
+                    let target = self.readlink(ino).ok_or(ParcelError::Enoent)?;
+                    header.set_entry_type(EntryType::Symlink);
+                    builder.append_link(
+                        &mut header,
+                        &path,
+                        Path::new(OsStr::from_bytes(&target)),
+                    )?;
+                }
+                InodeKind::CharDevice => {
+                    header.set_entry_type(EntryType::Char);
+                    header.set_device_major(libc::major(attr.rdev as u64))?;
+                    header.set_device_minor(libc::minor(attr.rdev as u64))?;
+                    builder.append_data(&mut header, &path, &[][..])?;
+                }
+                InodeKind::Whiteout => continue,
+            }
+            if kind == InodeKind::RegularFile {
+                written.insert(ino, path);
+            }
+        }
+        builder.into_inner()?.flush()?;
+        Ok(())
+    }
+}
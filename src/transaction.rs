@@ -0,0 +1,159 @@
+use std::{collections::BTreeMap, ffi::OsString, ops::Deref, path::PathBuf};
+
+use anyhow::Result;
+
+use crate::{metadata::ParcelMetadata, FileAdd, InodeAttr, InodeKind, ParcelHandle};
+
+/// A group of changes to a parcel that are kept or undone together. Changes are made through
+/// the transaction, which dereferences to the parcel for reading. Only changes that can be undone
+/// are offered, so the parcel can't be stored or given a new backing file while it is open:
+///
+/// ```compile_fail
+/// # let mut parcel = pyxis_parcel::ParcelHandle::new();
+/// let mut txn = parcel.begin();
+/// txn.store();
+/// ```
+///
+/// Writes to files that existed before the transaction began go to fresh space in the data
+/// section, so nothing the backing file's header refers to is touched and a rollback restores
+/// their contents.
+pub struct Transaction<'a> {
+    handle: &'a mut ParcelHandle,
+    done:   bool,
+}
+
+impl ParcelHandle {
+    /// Begin a transaction. Its changes are kept by [`Transaction::commit`] and undone by
+    /// [`Transaction::rollback`] or by dropping it.
+    pub fn begin(&mut self) -> Transaction<'_> {
+        self.savepoint();
+        Transaction {
+            handle: self,
+            done:   false,
+        }
+    }
+}
+
+impl Transaction<'_> {
+    /// Keep the transaction's changes
+    pub fn commit(mut self) {
+        self.handle.commit_savepoint();
+        self.done = true;
+    }
+
+    /// Undo the transaction's changes
+    pub fn rollback(self) {}
+
+    /// Begin a transaction within this one
+    pub fn begin(&mut self) -> Transaction<'_> {
+        self.handle.begin()
+    }
+    /// Add a file to the parcel
+    pub fn add_file(
+        &mut self,
+        from: FileAdd,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
+        self.handle.add_file(from, attrs, xattrs)
+    }
+    /// Add a directory to the parcel
+    pub fn add_directory(
+        &mut self,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
+        self.handle.add_directory(attrs, xattrs)
+    }
+    /// Add a symlink to the parcel
+    pub fn add_symlink(
+        &mut self,
+        target: OsString,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
+        self.handle.add_symlink(target, attrs, xattrs)
+    }
+    /// Add a hard link to an existing path in the parcel
+    pub fn add_hardlink(&mut self, target: OsString) -> Result<u64> {
+        self.handle.add_hardlink(target)
+    }
+    /// Add a character device to the parcel
+    pub fn add_char(
+        &mut self,
+        attrs: InodeAttr,
+        xattrs: BTreeMap<OsString, Vec<u8>>,
+    ) -> Result<u64> {
+        self.handle.add_char(attrs, xattrs)
+    }
+    /// Insert an entry to a directory mapping a filename to an inode
+    pub fn insert_dirent(
+        &mut self,
+        parent: u64,
+        name: OsString,
+        child: u64,
+        kind: InodeKind,
+    ) -> Result<()> {
+        self.handle.insert_dirent(parent, name, child, kind)
+    }
+    /// Insert a whiteout entry for a filename in a directory
+    pub fn insert_whiteout(&mut self, parent: u64, name: OsString) -> Result<()> {
+        self.handle.insert_whiteout(parent, name)
+    }
+    /// Delete an item from the parcel
+    pub fn delete(&mut self, ino: u64) -> Result<()> {
+        self.handle.delete(ino)
+    }
+    /// Read the contents of a file
+    pub fn read(&mut self, ino: u64, offset: u64, size: Option<u64>) -> Result<Vec<u8>> {
+        self.handle.read(ino, offset, size)
+    }
+    /// Write to a file
+    pub fn write(&mut self, ino: u64, offset: u64, buf: &[u8]) -> Result<u64> {
+        self.handle.write(ino, offset, buf)
+    }
+    /// Write to a file, expanding it if necessary
+    pub fn expand_write(&mut self, ino: u64, offset: u64, buf: &[u8]) -> Result<u64> {
+        self.handle.expand_write(ino, offset, buf)
+    }
+    /// Reallocate a file to allow it to grow
+    pub fn realloc_reserved(&mut self, ino: u64, capacity: u64) -> Result<()> {
+        self.handle.realloc_reserved(ino, capacity)
+    }
+    /// Get a mutable ref to the attributes of an inode
+    pub fn getattr_mut(&mut self, ino: u64) -> Option<&mut InodeAttr> {
+        self.handle.getattr_mut(ino)
+    }
+    /// Set the device number of an inode, keeping a character device's contents in step
+    pub fn set_rdev(&mut self, ino: u64, rdev: u64) -> Result<()> {
+        self.handle.set_rdev(ino, rdev)
+    }
+    /// Get a mutable ref to the extended attributes of an inode
+    pub fn getxattrs_mut(&mut self, ino: u64) -> Option<&mut BTreeMap<OsString, Vec<u8>>> {
+        self.handle.getxattrs_mut(ino)
+    }
+    /// Get a mutable reference to the parcel's metadata
+    pub fn metadata(&mut self) -> &mut ParcelMetadata {
+        self.handle.metadata()
+    }
+    /// Mark a regular file as a config file, to be protected on upgrade
+    pub fn mark_config(&mut self, path: PathBuf) -> Result<()> {
+        self.handle.mark_config(path)
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.handle.rollback_savepoint();
+        }
+    }
+}
+
+impl Deref for Transaction<'_> {
+    type Target = ParcelHandle;
+
+    fn deref(&self) -> &ParcelHandle {
+        self.handle
+    }
+}
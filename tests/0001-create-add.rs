@@ -9,7 +9,7 @@ use common::Fixture;
 fn empty_serialize() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel.store().unwrap();
     f.compare("empty_serialize.parcel");
 }
@@ -18,7 +18,7 @@ fn empty_serialize() {
 fn add_file_string() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel
         .add_file(
             FileAdd::Bytes(b"foo".to_vec()),
@@ -34,7 +34,7 @@ fn add_file_string() {
 fn add_file_file() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel
         .add_file(
             FileAdd::Name("tests/data/foo".into()),
@@ -50,7 +50,7 @@ fn add_file_file() {
 fn insert_file_dirent() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     let add = parcel
         .add_file(
             FileAdd::Bytes(b"foo".to_vec()),
@@ -69,8 +69,10 @@ fn insert_file_dirent() {
 fn add_dir() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
-    parcel.add_directory(Default::default(), Default::default());
+    parcel.set_file(f.make_rw()).unwrap();
+    parcel
+        .add_directory(Default::default(), Default::default())
+        .unwrap();
     parcel.store().unwrap();
     f.compare("add_dir.parcel");
 }
@@ -79,8 +81,10 @@ fn add_dir() {
 fn add_char() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
-    parcel.add_char(Default::default(), Default::default());
+    parcel.set_file(f.make_rw()).unwrap();
+    parcel
+        .add_char(Default::default(), Default::default())
+        .unwrap();
     parcel.store().unwrap();
     f.compare("add_char.parcel");
 }
@@ -89,7 +93,7 @@ fn add_char() {
 fn add_symlink() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel
         .add_symlink(
             OsString::from("foo"),
@@ -105,7 +109,7 @@ fn add_symlink() {
 fn add_hardlink() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     let add = parcel
         .add_file(
             FileAdd::Bytes(b"foo".to_vec()),
@@ -126,8 +130,10 @@ fn add_hardlink() {
 fn insert_dir_dirent() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
-    let add = parcel.add_directory(Default::default(), Default::default());
+    parcel.set_file(f.make_rw()).unwrap();
+    let add = parcel
+        .add_directory(Default::default(), Default::default())
+        .unwrap();
     parcel
         .insert_dirent(1, "foo".into(), add, InodeKind::Directory)
         .unwrap();
@@ -139,7 +145,7 @@ fn insert_dir_dirent() {
 fn add_multiple_files() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel
         .add_file(
             FileAdd::Bytes(b"foo".to_vec()),
@@ -169,7 +175,7 @@ fn add_multiple_files() {
 fn add_reload_add() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel
         .add_file(
             FileAdd::Bytes(b"foo".to_vec()),
@@ -197,7 +203,7 @@ fn add_reload_add() {
 fn add_reload_delete() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     let add = parcel
         .add_file(
             FileAdd::Bytes(b"foo".to_vec()),
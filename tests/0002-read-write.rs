@@ -8,7 +8,7 @@ use common::Fixture;
 fn add_read_no_flush() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel.store().unwrap();
     let ino = parcel
         .add_file(
@@ -24,7 +24,7 @@ fn add_read_no_flush() {
 fn add_read_flush() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel.store().unwrap();
     let ino = parcel
         .add_file(
@@ -42,7 +42,7 @@ fn add_read_flush() {
 fn read_after_resize() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel.store().unwrap();
     let ino = parcel
         .add_file(
@@ -70,7 +70,7 @@ fn read_after_resize() {
 fn write() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel.store().unwrap();
     let ino = parcel
         .add_file(
@@ -7,7 +7,7 @@ use common::Fixture;
 fn realloc_last() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel.store().unwrap();
     let ino = parcel
         .add_file(
@@ -26,7 +26,7 @@ fn realloc_last() {
 fn realloc_first() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel.store().unwrap();
     let ino = parcel
         .add_file(
@@ -52,7 +52,7 @@ fn realloc_first() {
 fn realloc_middle() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel.store().unwrap();
     parcel
         .add_file(
@@ -85,7 +85,7 @@ fn realloc_middle() {
 fn realloc_create() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
     parcel.store().unwrap();
     let ino = parcel
         .add_file(
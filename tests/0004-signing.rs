@@ -0,0 +1,168 @@
+use std::{fs, path::PathBuf};
+
+use pyxis_parcel::{FileAdd, InodeKind, ParcelError, ParcelHandle, SigningKey};
+
+mod common;
+use common::Fixture;
+
+fn signed_parcel(f: &Fixture, key: &SigningKey) -> u64 {
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let ino = parcel
+        .add_file(
+            FileAdd::Bytes(b"foo".to_vec()),
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+    parcel.store().unwrap();
+    parcel.sign(key).unwrap();
+    parcel.store().unwrap();
+    ino
+}
+
+#[test]
+fn sign_verify() {
+    let f = Fixture::blank("test.parcel");
+    let key = SigningKey::from_bytes(&[1; 32]);
+    signed_parcel(&f, &key);
+
+    let mut parcel = ParcelHandle::load_verified(f.make_rw(), &key.verifying_key()).unwrap();
+    assert_eq!(parcel.read(2, 0, None).unwrap(), b"foo");
+}
+
+#[test]
+fn verify_unsigned() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    parcel.store().unwrap();
+
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let err = ParcelHandle::load_verified(f.make_rw(), &key.verifying_key())
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::Unsigned)
+    ));
+}
+
+#[test]
+fn verify_wrong_key() {
+    let f = Fixture::blank("test.parcel");
+    signed_parcel(&f, &SigningKey::from_bytes(&[1; 32]));
+
+    let other = SigningKey::from_bytes(&[2; 32]);
+    let err = ParcelHandle::load_verified(f.make_rw(), &other.verifying_key())
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::BadSignature)
+    ));
+}
+
+#[test]
+fn verify_tampered_contents() {
+    let f = Fixture::blank("test.parcel");
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let ino = signed_parcel(&f, &key);
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    parcel.write(ino, 0, b"bar").unwrap();
+
+    let err = ParcelHandle::load_verified(f.make_rw(), &key.verifying_key())
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::DigestMismatch { ino: 2 })
+    ));
+}
+
+#[test]
+fn verify_forged_record() {
+    let f = Fixture::blank("test.parcel");
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    for (name, contents) in [("good", b"good"), ("evil", b"evil")] {
+        let ino = parcel
+            .add_file(
+                FileAdd::Bytes(contents.to_vec()),
+                Default::default(),
+                Default::default(),
+            )
+            .unwrap();
+        parcel
+            .insert_dirent(1, name.into(), ino, InodeKind::RegularFile)
+            .unwrap();
+    }
+    parcel.store().unwrap();
+    parcel.sign(&key).unwrap();
+    parcel.store().unwrap();
+    drop(parcel);
+
+    // Replace the good file's record with a copy of the evil file's, leaving the signature as
+    // it is, and index the records again. The header moves to the end of the file to make room.
+    let path = PathBuf::from(&f);
+    let mut bytes = fs::read(&path).unwrap();
+    let field = |bytes: &[u8], n: usize| {
+        let start = 5 + n * 17;
+        usize::from_str_radix(std::str::from_utf8(&bytes[start..start + 16]).unwrap(), 16).unwrap()
+    };
+    let (data, header, len) = (field(&bytes, 0), field(&bytes, 1), field(&bytes, 2));
+    let text = String::from_utf8(bytes[header..header + len].to_vec()).unwrap();
+    let (head, records) = text.split_once("records:\n").unwrap();
+    let (summary, _) = head.split_once("index: |\n").unwrap();
+    let mut split: Vec<String> =
+        records
+            .split_inclusive("\n")
+            .fold(Vec::new(), |mut split, line| {
+                match line.starts_with("  - ") {
+                    true => split.push(line.to_string()),
+                    false => split.last_mut().unwrap().push_str(line),
+                }
+                split
+            });
+    split[1] = split[2].replacen("ino: 3", "ino: 2", 1);
+    let mut index = String::new();
+    let mut offset = 0;
+    for (ino, record) in split.iter().enumerate() {
+        index.push_str(&format!(
+            "  {:016x} {:016x} {:08x}\n",
+            ino + 1,
+            offset,
+            record.len()
+        ));
+        offset += record.len();
+    }
+    let text = format!("{}index: |\n{}records:\n{}", summary, index, split.concat());
+
+    let header = bytes.len();
+    bytes.extend_from_slice(text.as_bytes());
+    bytes.extend_from_slice(b"\n...\n");
+    let layout = format!(
+        "@{:016x} {:016x} {:016x} {:016x}\n",
+        data,
+        header,
+        text.len(),
+        text.len() + 5
+    );
+    bytes[4..4 + layout.len()].copy_from_slice(layout.as_bytes());
+    fs::write(&path, bytes).unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    let good = parcel.select(PathBuf::from("/good")).unwrap();
+    assert_eq!(parcel.read(good, 0, None).unwrap(), b"evil");
+    drop(parcel);
+
+    let err = ParcelHandle::load_verified(f.make_rw(), &key.verifying_key())
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::BadSignature)
+    ));
+}
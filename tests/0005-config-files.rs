@@ -0,0 +1,171 @@
+use std::{fs, path::PathBuf};
+
+use pyxis_parcel::{ConfigPolicy, FileAdd, InodeAttr, InodeKind, ParcelError, ParcelHandle};
+
+mod common;
+use common::Fixture;
+
+fn config_parcel(f: &Fixture, contents: &[u8]) -> ParcelHandle {
+    let attrs = InodeAttr {
+        perm: 0o755,
+        ..Default::default()
+    };
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let etc = parcel.add_directory(attrs, Default::default()).unwrap();
+    parcel
+        .insert_dirent(1, "etc".into(), etc, InodeKind::Directory)
+        .unwrap();
+    let conf = parcel
+        .add_file(
+            FileAdd::Bytes(contents.to_vec()),
+            InodeAttr {
+                perm: 0o644,
+                ..Default::default()
+            },
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(etc, "foo.conf".into(), conf, InodeKind::RegularFile)
+        .unwrap();
+    parcel.mark_config(PathBuf::from("/etc/foo.conf")).unwrap();
+    parcel.store().unwrap();
+    parcel
+}
+
+#[test]
+fn mark_config() {
+    let f = Fixture::blank("test.parcel");
+    config_parcel(&f, b"foo");
+
+    let parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert!(parcel.is_config(PathBuf::from("/etc/foo.conf")));
+    assert!(parcel.is_config(PathBuf::from("etc/./foo.conf")));
+    assert!(!parcel.is_config(PathBuf::from("/etc")));
+}
+
+#[test]
+fn extract_policies() {
+    let old = Fixture::blank("old.parcel");
+    let new = Fixture::blank("new.parcel");
+    let root = tempfile::tempdir().unwrap();
+    let conf = root.path().join("etc/foo.conf");
+    let conf_new = root.path().join("etc/foo.conf.new");
+
+    let mut old = config_parcel(&old, b"foo");
+    old.extract(root.path(), &ConfigPolicy::KeepExisting)
+        .unwrap();
+    assert_eq!(fs::read(&conf).unwrap(), b"foo");
+
+    let mut new = config_parcel(&new, b"bar");
+
+    // Unmodified since the previous install: safe to replace
+    let previous = old.config_digests().unwrap();
+    new.extract(
+        root.path(),
+        &ConfigPolicy::ReplaceUnmodified(previous.clone()),
+    )
+    .unwrap();
+    assert_eq!(fs::read(&conf).unwrap(), b"bar");
+    assert!(!conf_new.exists());
+
+    // Locally modified: keep it and write the new contents alongside
+    fs::write(&conf, b"local").unwrap();
+    new.extract(root.path(), &ConfigPolicy::ReplaceUnmodified(previous))
+        .unwrap();
+    assert_eq!(fs::read(&conf).unwrap(), b"local");
+    assert_eq!(fs::read(&conf_new).unwrap(), b"bar");
+    fs::remove_file(&conf_new).unwrap();
+
+    new.extract(root.path(), &ConfigPolicy::KeepExisting)
+        .unwrap();
+    assert_eq!(fs::read(&conf).unwrap(), b"local");
+    assert!(!conf_new.exists());
+
+    new.extract(root.path(), &ConfigPolicy::WriteNew).unwrap();
+    assert_eq!(fs::read(&conf).unwrap(), b"local");
+    assert_eq!(fs::read(&conf_new).unwrap(), b"bar");
+}
+
+#[test]
+fn extract_over_existing() {
+    let f = Fixture::blank("test.parcel");
+    let root = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    let mut parcel = config_parcel(&f, b"foo");
+    let conf = parcel.select(PathBuf::from("/etc/foo.conf")).unwrap();
+    parcel
+        .insert_dirent(1, "link".into(), conf, InodeKind::RegularFile)
+        .unwrap();
+    let plain = parcel
+        .add_file(
+            FileAdd::Bytes(b"plain".to_vec()),
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(1, "plain".into(), plain, InodeKind::RegularFile)
+        .unwrap();
+    parcel.store().unwrap();
+
+    parcel
+        .extract(root.path(), &ConfigPolicy::KeepExisting)
+        .unwrap();
+    // Symlinks left where files and directories go are replaced, not followed
+    let target = outside.path().join("target");
+    fs::write(&target, b"untouched").unwrap();
+    fs::remove_file(root.path().join("plain")).unwrap();
+    std::os::unix::fs::symlink(&target, root.path().join("plain")).unwrap();
+    fs::remove_file(root.path().join("etc/foo.conf")).unwrap();
+    fs::remove_file(root.path().join("link")).unwrap();
+    fs::remove_dir(root.path().join("etc")).unwrap();
+    std::os::unix::fs::symlink(outside.path(), root.path().join("etc")).unwrap();
+
+    parcel
+        .extract(root.path(), &ConfigPolicy::KeepExisting)
+        .unwrap();
+    assert_eq!(fs::read(&target).unwrap(), b"untouched");
+    assert!(!outside.path().join("foo.conf").exists());
+    assert!(fs::symlink_metadata(root.path().join("plain"))
+        .unwrap()
+        .is_file());
+    assert_eq!(fs::read(root.path().join("plain")).unwrap(), b"plain");
+    assert!(fs::symlink_metadata(root.path().join("etc"))
+        .unwrap()
+        .is_dir());
+    assert_eq!(fs::read(root.path().join("link")).unwrap(), b"foo");
+
+    // Extracting again over an identical tree replaces the hard link
+    parcel
+        .extract(root.path(), &ConfigPolicy::KeepExisting)
+        .unwrap();
+    assert_eq!(fs::read(root.path().join("link")).unwrap(), b"foo");
+}
+
+#[test]
+fn extract_bad_names() {
+    let root = tempfile::tempdir().unwrap();
+    for name in ["..", ".", "a/b", ""] {
+        let f = Fixture::blank("test.parcel");
+        let mut parcel = config_parcel(&f, b"foo");
+        let etc = parcel.select(PathBuf::from("/etc")).unwrap();
+        let conf = parcel.select(PathBuf::from("/etc/foo.conf")).unwrap();
+        parcel
+            .insert_dirent(etc, name.into(), conf, InodeKind::RegularFile)
+            .unwrap();
+        parcel.store().unwrap();
+
+        let err = parcel
+            .extract(root.path(), &ConfigPolicy::KeepExisting)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err.downcast_ref::<ParcelError>(), Some(ParcelError::BadName(n)) if n == name),
+            "{:?}",
+            name
+        );
+    }
+    assert!(!root.path().join("foo.conf").exists());
+}
@@ -0,0 +1,112 @@
+use std::{ffi::OsString, fs, path::PathBuf};
+
+use pyxis_parcel::{InodeKind, ParcelHandle};
+use tar::{Builder, EntryType, Header};
+
+mod common;
+use common::Fixture;
+
+fn header(kind: EntryType, mode: u32, size: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(kind);
+    header.set_mode(mode);
+    header.set_size(size);
+    header.set_uid(1000);
+    header.set_gid(100);
+    header.set_mtime(1234);
+    header
+}
+
+fn tarball() -> Vec<u8> {
+    let mut builder = Builder::new(Vec::new());
+
+    let mut h = header(EntryType::Directory, 0o750, 0);
+    builder.append_data(&mut h, "usr/", &[][..]).unwrap();
+
+    builder
+        .append_pax_extensions([("SCHILY.xattr.user.foo", &b"bar"[..])])
+        .unwrap();
+    let mut h = header(EntryType::Regular, 0o644, 3);
+    builder
+        .append_data(&mut h, "./usr/bin/foo", &b"foo"[..])
+        .unwrap();
+
+    let mut h = header(EntryType::Link, 0o644, 0);
+    builder
+        .append_link(&mut h, "usr/bin/bar", "usr/bin/foo")
+        .unwrap();
+
+    let mut h = header(EntryType::Symlink, 0o777, 0);
+    builder.append_link(&mut h, "usr/baz", "bin/foo").unwrap();
+
+    let mut h = header(EntryType::Char, 0o666, 0);
+    h.set_device_major(1).unwrap();
+    h.set_device_minor(3).unwrap();
+    builder.append_data(&mut h, "dev/null", &[][..]).unwrap();
+
+    builder.into_inner().unwrap()
+}
+
+#[test]
+fn import_tar() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    parcel.import_tar(&tarball()[..]).unwrap();
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+
+    let usr = parcel.select(PathBuf::from("/usr")).unwrap();
+    let attr = parcel.getattr(usr).unwrap();
+    assert_eq!(attr.kind, InodeKind::Directory);
+    assert_eq!(attr.perm as u32, libc::S_IFDIR | 0o750);
+    assert_eq!((attr.uid, attr.gid), (1000, 100));
+
+    let foo = parcel.select(PathBuf::from("/usr/bin/foo")).unwrap();
+    assert_eq!(parcel.read(foo, 0, None).unwrap(), b"foo");
+    let attr = parcel.getattr(foo).unwrap();
+    assert_eq!(attr.perm as u32, libc::S_IFREG | 0o644);
+    assert_eq!(attr.nlink, 2);
+    assert_eq!(
+        parcel.getxattrs(foo).unwrap()[&OsString::from("user.foo")],
+        b"bar"
+    );
+
+    assert_eq!(parcel.select(PathBuf::from("/usr/bin/bar")), Some(foo));
+
+    let baz = parcel.select(PathBuf::from("/usr/baz")).unwrap();
+    assert_eq!(parcel.readlink(baz).unwrap(), b"bin/foo");
+
+    let null = parcel.select(PathBuf::from("/dev/null")).unwrap();
+    let attr = parcel.getattr(null).unwrap();
+    assert_eq!(attr.kind, InodeKind::CharDevice);
+    assert_eq!(attr.rdev as u64, libc::makedev(1, 3));
+}
+
+#[test]
+fn import_tar_large_members() {
+    const SIZE: usize = 4 << 20;
+    let contents: Vec<u8> = (0..SIZE).map(|i| (i % 251) as u8).collect();
+    let mut builder = Builder::new(Vec::new());
+    for name in ["a", "b"] {
+        let mut h = header(EntryType::Regular, 0o644, SIZE as u64);
+        builder.append_data(&mut h, name, &contents[..]).unwrap();
+    }
+    let tarball = builder.into_inner().unwrap();
+
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    parcel.import_tar(&tarball[..]).unwrap();
+    // Members are copied into the data section as they're read, and identical ones share it
+    assert_eq!(parcel.dedup_savings().files, 1);
+    parcel.store().unwrap();
+    assert!(fs::metadata(PathBuf::from(&f)).unwrap().len() < 2 * SIZE as u64);
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    for name in ["/a", "/b"] {
+        let ino = parcel.select(PathBuf::from(name)).unwrap();
+        assert_eq!(parcel.read(ino, 0, None).unwrap(), contents);
+    }
+}
@@ -0,0 +1,150 @@
+use std::{collections::BTreeMap, ffi::OsString, io::Read, path::PathBuf};
+
+use pyxis_parcel::{FileAdd, InodeAttr, InodeKind, ParcelHandle};
+use tar::{Archive, EntryType};
+
+mod common;
+use common::Fixture;
+
+fn source_parcel(f: &Fixture) -> ParcelHandle {
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let dir = parcel
+        .add_directory(
+            InodeAttr {
+                perm: libc::S_IFDIR | 0o750,
+                uid: 1000,
+                ..Default::default()
+            },
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(1, "usr".into(), dir, InodeKind::Directory)
+        .unwrap();
+    let mut xattrs = BTreeMap::new();
+    xattrs.insert(OsString::from("user.foo"), b"bar".to_vec());
+    let file = parcel
+        .add_file(
+            FileAdd::Bytes(b"foo".to_vec()),
+            InodeAttr {
+                perm: libc::S_IFREG | 0o644,
+                ..Default::default()
+            },
+            xattrs,
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(dir, "foo".into(), file, InodeKind::RegularFile)
+        .unwrap();
+    parcel
+        .insert_dirent(dir, "hard".into(), file, InodeKind::RegularFile)
+        .unwrap();
+    let link = parcel
+        .add_symlink("foo".into(), Default::default(), Default::default())
+        .unwrap();
+    parcel
+        .insert_dirent(dir, "soft".into(), link, InodeKind::Symlink)
+        .unwrap();
+    let null = parcel
+        .add_char(
+            InodeAttr {
+                perm: libc::S_IFCHR | 0o666,
+                rdev: libc::makedev(1, 3),
+                ..Default::default()
+            },
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(1, "null".into(), null, InodeKind::CharDevice)
+        .unwrap();
+    parcel.store().unwrap();
+    parcel
+}
+
+#[test]
+fn export_tar() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = source_parcel(&f);
+    let mut out = Vec::new();
+    parcel.export_tar(PathBuf::from("/"), &mut out).unwrap();
+
+    let mut archive = Archive::new(&out[..]);
+    let members: Vec<(String, EntryType, u32)> = archive
+        .entries()
+        .unwrap()
+        .map(|e| {
+            let e = e.unwrap();
+            (
+                e.path().unwrap().to_string_lossy().into_owned(),
+                e.header().entry_type(),
+                e.header().mode().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        members,
+        vec![
+            ("null".to_string(), EntryType::Char, 0o666),
+            ("usr".to_string(), EntryType::Directory, 0o750),
+            ("usr/foo".to_string(), EntryType::Regular, 0o644),
+            ("usr/hard".to_string(), EntryType::Link, 0o644),
+            ("usr/soft".to_string(), EntryType::Symlink, 0),
+        ]
+    );
+}
+
+#[test]
+fn export_import_roundtrip() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = source_parcel(&f);
+    let mut out = Vec::new();
+    parcel.export_tar(PathBuf::from("/usr"), &mut out).unwrap();
+
+    let g = Fixture::blank("copy.parcel");
+    let mut copy = ParcelHandle::new();
+    copy.set_file(g.make_rw()).unwrap();
+    copy.import_tar(&out[..]).unwrap();
+    copy.store().unwrap();
+
+    let foo = copy.select(PathBuf::from("/foo")).unwrap();
+    assert_eq!(copy.read(foo, 0, None).unwrap(), b"foo");
+    assert_eq!(copy.select(PathBuf::from("/hard")), Some(foo));
+    assert_eq!(
+        copy.getxattrs(foo).unwrap()[&OsString::from("user.foo")],
+        b"bar"
+    );
+    let soft = copy.select(PathBuf::from("/soft")).unwrap();
+    assert_eq!(copy.readlink(soft).unwrap(), b"foo");
+    assert_eq!(copy.select(PathBuf::from("/null")), None);
+}
+
+#[test]
+fn export_tar_large_file() {
+    const SIZE: usize = (1 << 20) + 123;
+    let contents: Vec<u8> = (0..SIZE).map(|i| (i % 251) as u8).collect();
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let ino = parcel
+        .add_file(
+            FileAdd::Bytes(contents.clone()),
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(1, "large".into(), ino, InodeKind::RegularFile)
+        .unwrap();
+    parcel.store().unwrap();
+
+    let mut out = Vec::new();
+    parcel.export_tar(PathBuf::from("/"), &mut out).unwrap();
+    let mut archive = Archive::new(&out[..]);
+    let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+    assert_eq!(entry.header().size().unwrap(), SIZE as u64);
+    let mut exported = Vec::new();
+    entry.read_to_end(&mut exported).unwrap();
+    assert_eq!(exported, contents);
+}
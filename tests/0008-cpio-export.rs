@@ -0,0 +1,140 @@
+use std::path::PathBuf;
+
+use pyxis_parcel::{FileAdd, InodeAttr, InodeKind, ParcelHandle};
+
+mod common;
+use common::Fixture;
+
+/// A parsed newc member: (name, mode, nlink, rdevmajor, rdevminor, data)
+type Member = (String, u32, u32, u32, u32, Vec<u8>);
+
+fn parse_newc(mut buf: &[u8]) -> Vec<Member> {
+    let mut res = Vec::new();
+    loop {
+        assert_eq!(&buf[..6], b"070701");
+        let field = |i: usize| {
+            u32::from_str_radix(
+                std::str::from_utf8(&buf[6 + i * 8..14 + i * 8]).unwrap(),
+                16,
+            )
+            .unwrap()
+        };
+        let (mode, nlink, size) = (field(1), field(4), field(6) as usize);
+        let (major, minor, namesize) = (field(9), field(10), field(11) as usize);
+        let name = String::from_utf8(buf[110..110 + namesize - 1].to_vec()).unwrap();
+        let data_start = (110 + namesize + 3) & !3;
+        let data = buf[data_start..data_start + size].to_vec();
+        buf = &buf[(data_start + size + 3) & !3..];
+        if name == "TRAILER!!!" {
+            assert!(buf.is_empty());
+            return res;
+        }
+        res.push((name, mode, nlink, major, minor, data));
+    }
+}
+
+fn add_file(parcel: &mut ParcelHandle, dir: u64, name: &str, contents: &[u8]) -> u64 {
+    let ino = parcel
+        .add_file(
+            FileAdd::Bytes(contents.to_vec()),
+            InodeAttr {
+                perm: 0o644,
+                ..Default::default()
+            },
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(dir, name.into(), ino, InodeKind::RegularFile)
+        .unwrap();
+    ino
+}
+
+fn add_dir(parcel: &mut ParcelHandle, name: &str) -> u64 {
+    let ino = parcel
+        .add_directory(
+            InodeAttr {
+                perm: 0o755,
+                ..Default::default()
+            },
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(1, name.into(), ino, InodeKind::Directory)
+        .unwrap();
+    ino
+}
+
+#[test]
+fn export_cpio() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let dev = add_dir(&mut parcel, "dev");
+    let console = parcel
+        .add_char(
+            InodeAttr {
+                perm: 0o600,
+                rdev: libc::makedev(5, 1),
+                ..Default::default()
+            },
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(dev, "console".into(), console, InodeKind::CharDevice)
+        .unwrap();
+    let sh = add_file(&mut parcel, 1, "sh", b"#!");
+    parcel
+        .insert_dirent(1, "sh2".into(), sh, InodeKind::RegularFile)
+        .unwrap();
+    parcel.store().unwrap();
+
+    let mut out = Vec::new();
+    parcel.export_cpio(PathBuf::from("/"), &mut out).unwrap();
+    assert_eq!(
+        parse_newc(&out),
+        vec![
+            ("dev".into(), libc::S_IFDIR | 0o755, 1, 0, 0, vec![]),
+            ("dev/console".into(), libc::S_IFCHR | 0o600, 1, 5, 1, vec![]),
+            ("sh".into(), libc::S_IFREG | 0o644, 2, 0, 0, b"#!".to_vec()),
+            ("sh2".into(), libc::S_IFREG | 0o644, 2, 0, 0, vec![]),
+        ]
+    );
+}
+
+#[test]
+fn export_cpio_overlay() {
+    let lower_f = Fixture::blank("lower.parcel");
+    let mut lower = ParcelHandle::new();
+    lower.set_file(lower_f.make_rw()).unwrap();
+    let etc = add_dir(&mut lower, "etc");
+    add_file(&mut lower, etc, "passwd", b"root");
+    add_file(&mut lower, etc, "motd", b"hi");
+    let opt = add_dir(&mut lower, "opt");
+    add_file(&mut lower, opt, "tool", b"old");
+    lower.store().unwrap();
+
+    let upper_f = Fixture::blank("upper.parcel");
+    let mut upper = ParcelHandle::new();
+    upper.set_file(upper_f.make_rw()).unwrap();
+    let etc = add_dir(&mut upper, "etc");
+    upper.insert_whiteout(etc, "passwd".into()).unwrap();
+    add_file(&mut upper, etc, "motd", b"bye");
+    add_file(&mut upper, 1, "opt", b"not a dir");
+    upper.store().unwrap();
+
+    let mut out = Vec::new();
+    ParcelHandle::export_cpio_overlay(&mut [lower, upper], PathBuf::from("/"), &mut out).unwrap();
+    let members: Vec<(String, Vec<u8>)> =
+        parse_newc(&out).into_iter().map(|m| (m.0, m.5)).collect();
+    assert_eq!(
+        members,
+        vec![
+            ("etc".into(), vec![]),
+            ("etc/motd".into(), b"bye".to_vec()),
+            ("opt".into(), b"not a dir".to_vec()),
+        ]
+    );
+}
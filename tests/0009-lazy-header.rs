@@ -0,0 +1,137 @@
+use std::{fs, path::PathBuf};
+
+use pyxis_parcel::{FileAdd, InodeKind, ParcelError, ParcelHandle};
+
+mod common;
+use common::Fixture;
+
+fn nested_parcel(f: &Fixture) {
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let dir = parcel
+        .add_directory(Default::default(), Default::default())
+        .unwrap();
+    parcel
+        .insert_dirent(1, "dir".into(), dir, InodeKind::Directory)
+        .unwrap();
+    for (name, contents) in [("foo", b"foo"), ("bar", b"bar")] {
+        let ino = parcel
+            .add_file(
+                FileAdd::Bytes(contents.to_vec()),
+                Default::default(),
+                Default::default(),
+            )
+            .unwrap();
+        parcel
+            .insert_dirent(dir, name.into(), ino, InodeKind::RegularFile)
+            .unwrap();
+    }
+    parcel.store().unwrap();
+}
+
+/// Replace text in a stored parcel with text of the same length
+fn corrupt(f: &Fixture, from: &str, to: &str) {
+    assert_eq!(from.len(), to.len());
+    let text = fs::read_to_string(PathBuf::from(f)).unwrap();
+    assert!(text.contains(from));
+    fs::write(PathBuf::from(f), text.replacen(from, to, 1)).unwrap();
+}
+
+fn is_corrupt(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::CorruptHeader)
+    )
+}
+
+#[test]
+fn lazy_select_read() {
+    let f = Fixture::blank("test.parcel");
+    nested_parcel(&f);
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    let ino = parcel.select(PathBuf::from("/dir/bar")).unwrap();
+    assert_eq!(parcel.getattr(ino).unwrap().size, 3);
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"bar");
+    assert_eq!(parcel.select(PathBuf::from("/dir/baz")), None);
+    assert!(!parcel.exists(42));
+}
+
+#[test]
+fn lazy_reload_store() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let add = parcel
+        .add_file(
+            FileAdd::Bytes(b"foo".to_vec()),
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(1, "foo".into(), add, InodeKind::RegularFile)
+        .unwrap();
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    parcel.store().unwrap();
+    f.compare("insert_file_dirent.parcel");
+}
+
+#[test]
+fn lazy_modify() {
+    let f = Fixture::blank("test.parcel");
+    nested_parcel(&f);
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    let dir = parcel.select(PathBuf::from("/dir")).unwrap();
+    let ino = parcel
+        .add_file(
+            FileAdd::Bytes(b"baz".to_vec()),
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(dir, "baz".into(), ino, InodeKind::RegularFile)
+        .unwrap();
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    for (name, contents) in [("foo", b"foo"), ("bar", b"bar"), ("baz", b"baz")] {
+        let ino = parcel.select(PathBuf::from("/dir").join(name)).unwrap();
+        assert_eq!(parcel.read(ino, 0, None).unwrap(), contents);
+    }
+}
+
+#[test]
+fn corrupt_index() {
+    let f = Fixture::blank("test.parcel");
+    nested_parcel(&f);
+    let text = fs::read_to_string(PathBuf::from(&f)).unwrap();
+    let line = text
+        .lines()
+        .find(|l| l.starts_with("  0000000000000002 "))
+        .unwrap();
+    corrupt(&f, line, &line.replace(" 0000", " 0001"));
+
+    let err = ParcelHandle::load(f.make_rw()).err().unwrap();
+    assert!(is_corrupt(&err));
+}
+
+#[test]
+fn corrupt_record() {
+    let f = Fixture::blank("test.parcel");
+    nested_parcel(&f);
+    corrupt(&f, "kind: RegularFile", "kind: RegularFail");
+
+    // The rest of the parcel can still be read, but nothing that needs every record works
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    let ino = parcel.select(PathBuf::from("/dir/foo")).unwrap();
+    assert!(parcel.getattr(ino).is_none());
+    let bar = parcel.select(PathBuf::from("/dir/bar")).unwrap();
+    assert_eq!(parcel.read(bar, 0, None).unwrap(), b"bar");
+    assert!(is_corrupt(&parcel.check().err().unwrap()));
+    assert!(is_corrupt(&parcel.store().err().unwrap()));
+}
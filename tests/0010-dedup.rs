@@ -0,0 +1,55 @@
+use std::{fs, path::PathBuf};
+
+use pyxis_parcel::{DedupSavings, ParcelHandle};
+
+mod common;
+use common::{add_file, Fixture};
+
+#[test]
+fn dedup_identical() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let a = add_file(&mut parcel, 1, "a", b"foo");
+    let b = add_file(&mut parcel, 1, "b", b"bar");
+    let c = add_file(&mut parcel, 1, "c", b"foo");
+    add_file(&mut parcel, 1, "empty", b"");
+    parcel.store().unwrap();
+    assert_eq!(parcel.dedup_savings(), DedupSavings { files: 1, bytes: 3 });
+    // The shared contents are stored once, followed by the header
+    let stored = fs::read(PathBuf::from(&f)).unwrap();
+    let data = parcel.data_position(a).unwrap() as usize;
+    assert!(stored[data..].starts_with(b"foobar---\n"));
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.read(a, 0, None).unwrap(), b"foo");
+    assert_eq!(parcel.read(b, 0, None).unwrap(), b"bar");
+    assert_eq!(parcel.read(c, 0, None).unwrap(), b"foo");
+}
+
+#[test]
+fn dedup_copy_on_write() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let a = add_file(&mut parcel, 1, "a", b"foo");
+    let b = add_file(&mut parcel, 1, "b", b"foo");
+    let c = add_file(&mut parcel, 1, "c", b"foo");
+    parcel.store().unwrap();
+
+    parcel.write(b, 0, b"b").unwrap();
+    parcel.expand_write(c, 3, b"bar").unwrap();
+    parcel.store().unwrap();
+    assert_eq!(parcel.dedup_savings(), DedupSavings::default());
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.read(a, 0, None).unwrap(), b"foo");
+    assert_eq!(parcel.read(b, 0, None).unwrap(), b"boo");
+    assert_eq!(parcel.read(c, 0, None).unwrap(), b"foobar");
+
+    // Modified contents can no longer be shared
+    let d = add_file(&mut parcel, 1, "d", b"boo");
+    parcel.store().unwrap();
+    assert_eq!(parcel.read(d, 0, None).unwrap(), b"boo");
+    assert_eq!(parcel.dedup_savings(), DedupSavings::default());
+}
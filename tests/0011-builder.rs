@@ -0,0 +1,52 @@
+use std::{fs, os::unix::fs::symlink, path::PathBuf};
+
+use pyxis_parcel::{ParcelBuilder, ParcelHandle};
+
+mod common;
+use common::Fixture;
+
+fn build(f: &Fixture, input: &std::path::Path, threads: usize) -> Vec<u8> {
+    let mut parcel = ParcelHandle::new();
+    ParcelBuilder::new()
+        .threads(threads)
+        .add_dir(&mut parcel, input)
+        .unwrap();
+    parcel.set_file(f.make_rw()).unwrap();
+    parcel.store().unwrap();
+    fs::read(PathBuf::from(f)).unwrap()
+}
+
+#[test]
+fn build_thread_count_independent() {
+    let input = tempfile::tempdir().unwrap();
+    for dir in 0..8 {
+        let dir = input.path().join(format!("dir{}", dir));
+        fs::create_dir(&dir).unwrap();
+        for file in 0..64 {
+            fs::write(
+                dir.join(format!("file{}", file)),
+                format!("contents {}", file % 5),
+            )
+            .unwrap();
+        }
+        fs::write(dir.join("empty"), b"").unwrap();
+        symlink("file0", dir.join("link")).unwrap();
+    }
+
+    // The first build's reads bump atimes, so only compare builds after it
+    let warmup = Fixture::blank("warmup.parcel");
+    build(&warmup, input.path(), 1);
+
+    let single = Fixture::blank("single.parcel");
+    let multi = Fixture::blank("multi.parcel");
+    assert_eq!(
+        build(&single, input.path(), 1),
+        build(&multi, input.path(), 8)
+    );
+
+    let mut parcel = ParcelHandle::load(multi.make_rw()).unwrap();
+    let ino = parcel.select(PathBuf::from("/dir3/file7")).unwrap();
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"contents 2");
+    let ino = parcel.select(PathBuf::from("/dir3/link")).unwrap();
+    assert_eq!(parcel.readlink(ino).unwrap(), b"file0");
+}
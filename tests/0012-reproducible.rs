@@ -0,0 +1,61 @@
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use pyxis_parcel::{ParcelBuilder, ParcelHandle};
+
+mod common;
+use common::Fixture;
+
+const NAMES: &[&str] = &["b", "a", "d/x", "c", "d/y", "d/e/z"];
+
+fn make_tree(root: &Path, names: &[&str], mode: u32) {
+    for name in names {
+        let path = root.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, name.as_bytes()).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+}
+
+fn build(f: &Fixture, input: &Path) -> Vec<u8> {
+    let mut parcel = ParcelHandle::new();
+    ParcelBuilder::new()
+        .reproducible(UNIX_EPOCH + Duration::from_secs(1_000_000))
+        .add_dir(&mut parcel, input)
+        .unwrap();
+    parcel.set_file(f.make_rw()).unwrap();
+    parcel.store().unwrap();
+    fs::read(PathBuf::from(f)).unwrap()
+}
+
+#[test]
+fn reproducible_build() {
+    let forward = tempfile::tempdir().unwrap();
+    make_tree(forward.path(), NAMES, 0o600);
+    let reversed = tempfile::tempdir().unwrap();
+    let mut names = NAMES.to_vec();
+    names.reverse();
+    make_tree(reversed.path(), &names, 0o640);
+
+    let first = Fixture::blank("first.parcel");
+    let second = Fixture::blank("second.parcel");
+    assert_eq!(
+        build(&first, forward.path()),
+        build(&second, reversed.path())
+    );
+
+    let parcel = ParcelHandle::load(first.make_rw()).unwrap();
+    let attr = parcel
+        .getattr(parcel.select(PathBuf::from("/d/y")).unwrap())
+        .unwrap();
+    assert_eq!(attr.mtime, UNIX_EPOCH + Duration::from_secs(1_000_000));
+    assert_eq!((attr.uid, attr.gid), (0, 0));
+    assert_eq!(attr.perm & 0o7777, 0o644);
+    // Inodes follow sorted path order: /, a, b, c, d, d/e, d/e/z, ...
+    assert_eq!(parcel.select(PathBuf::from("/a")), Some(2));
+    assert_eq!(parcel.select(PathBuf::from("/d/e/z")), Some(7));
+}
@@ -0,0 +1,76 @@
+use std::{fs, path::PathBuf};
+
+use pyxis_parcel::{ParcelBuilder, ParcelHandle};
+
+#[test]
+fn filter_remap() {
+    let input = tempfile::tempdir().unwrap();
+    for name in [
+        "bin/tool",
+        "share/doc/README",
+        "share/locale/de/tool.mo",
+        "share/locale/en/tool.mo",
+        "lib/libtool.a",
+        "lib/libtool.so",
+    ] {
+        let path = input.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, name).unwrap();
+    }
+
+    let mut parcel = ParcelHandle::new();
+    ParcelBuilder::new()
+        .exclude("share/doc")
+        .exclude("/share/locale/*")
+        .include("/share/locale/en")
+        .exclude("*.a")
+        .add_dir_at(&mut parcel, input.path(), &PathBuf::from("/usr/local"))
+        .unwrap();
+
+    let exists = |path: &str| parcel.select(PathBuf::from(path)).is_some();
+    assert!(exists("/usr/local/bin/tool"));
+    assert!(exists("/usr/local/lib/libtool.so"));
+    assert!(exists("/usr/local/share/locale/en/tool.mo"));
+    assert!(!exists("/usr/local/lib/libtool.a"));
+    assert!(!exists("/usr/local/share/doc"));
+    assert!(!exists("/usr/local/share/locale/de"));
+    assert!(!exists("/bin"));
+}
+
+#[test]
+fn include_beneath_exclusion() {
+    let input = tempfile::tempdir().unwrap();
+    for name in [
+        "share/doc/README",
+        "share/locale/de/tool.mo",
+        "share/locale/en/tool.mo",
+        "share/locale/en/extra.txt",
+        "share/man/tool.1",
+    ] {
+        let path = input.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, name).unwrap();
+    }
+
+    let mut parcel = ParcelHandle::new();
+    ParcelBuilder::new()
+        .exclude("share")
+        .include("/share/locale/en")
+        .include("*.1")
+        .add_dir(&mut parcel, input.path())
+        .unwrap();
+
+    let exists = |path: &str| parcel.select(PathBuf::from(path)).is_some();
+    assert!(exists("/share/locale/en/tool.mo"));
+    assert!(exists("/share/locale/en/extra.txt"));
+    assert!(exists("/share/man/tool.1"));
+    assert!(!exists("/share/locale/de"));
+    assert!(!exists("/share/doc"));
+    assert_eq!(
+        parcel
+            .readdir(parcel.select(PathBuf::from("/share")).unwrap())
+            .unwrap()
+            .len(),
+        2
+    );
+}
@@ -0,0 +1,105 @@
+use std::{fs, path::PathBuf};
+
+use pyxis_parcel::{FileAdd, InodeKind, Manifest, ParcelError, ParcelHandle};
+
+mod common;
+use common::Fixture;
+
+fn parcel_with_sudo() -> ParcelHandle {
+    let mut parcel = ParcelHandle::new();
+    let bin = parcel
+        .add_directory(Default::default(), Default::default())
+        .unwrap();
+    parcel
+        .insert_dirent(1, "bin".into(), bin, InodeKind::Directory)
+        .unwrap();
+    let attrs = pyxis_parcel::InodeAttr {
+        perm: libc::S_IFREG | 0o755,
+        uid: 1000,
+        gid: 1000,
+        ..Default::default()
+    };
+    let sudo = parcel
+        .add_file(FileAdd::Bytes(b"sudo".to_vec()), attrs, Default::default())
+        .unwrap();
+    parcel
+        .insert_dirent(bin, "sudo".into(), sudo, InodeKind::RegularFile)
+        .unwrap();
+    parcel
+}
+
+#[test]
+fn manifest_overrides() {
+    let manifest = Manifest::parse(
+        "# ownership fixups\n\
+         ./bin/sudo uid=0 gid=0 mode=4755\n\
+         /dev/null type=char mode=0666 device=1,3\n\
+         /dev/my\\040tty type=char device=4,1\n\
+         /usr/bin/sh type=link link=/bin/busybox\n",
+    )
+    .unwrap();
+    let mut parcel = parcel_with_sudo();
+    parcel.apply_manifest(&manifest).unwrap();
+
+    let attr = parcel
+        .getattr(parcel.select(PathBuf::from("/bin/sudo")).unwrap())
+        .unwrap();
+    assert_eq!((attr.uid, attr.gid), (0, 0));
+    assert_eq!(attr.perm as u32, libc::S_IFREG | 0o4755);
+
+    let attr = parcel
+        .getattr(parcel.select(PathBuf::from("/dev/null")).unwrap())
+        .unwrap();
+    assert_eq!(attr.kind, InodeKind::CharDevice);
+    assert_eq!(attr.perm as u32, libc::S_IFCHR | 0o666);
+    assert_eq!(attr.rdev as u64, libc::makedev(1, 3));
+    assert!(parcel.select(PathBuf::from("/dev/my tty")).is_some());
+
+    let sh = parcel.select(PathBuf::from("/usr/bin/sh")).unwrap();
+    assert_eq!(parcel.readlink(sh).unwrap(), b"/bin/busybox");
+}
+
+#[test]
+fn manifest_device_override() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let attrs = pyxis_parcel::InodeAttr {
+        perm: libc::S_IFCHR | 0o666,
+        rdev: libc::makedev(4, 1),
+        ..Default::default()
+    };
+    let tty = parcel.add_char(attrs, Default::default()).unwrap();
+    parcel
+        .insert_dirent(1, "tty".into(), tty, InodeKind::CharDevice)
+        .unwrap();
+    parcel
+        .apply_manifest(&Manifest::parse("/tty device=1,3\n").unwrap())
+        .unwrap();
+    parcel.store().unwrap();
+
+    let header = String::from_utf8_lossy(&fs::read(PathBuf::from(&f)).unwrap()).into_owned();
+    assert!(header.contains(&format!("Char: {}\n", libc::makedev(1, 3))));
+    let parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(
+        parcel.getattr(tty).unwrap().rdev as u64,
+        libc::makedev(1, 3)
+    );
+}
+
+#[test]
+fn manifest_errors() {
+    let err = Manifest::parse("/bin/sudo mode=99\n").err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::ManifestSyntax { line: 1, .. })
+    ));
+
+    // Regular files can't be conjured from a manifest
+    let manifest = Manifest::parse("/bin/ls type=file mode=0755\n").unwrap();
+    let err = parcel_with_sudo().apply_manifest(&manifest).err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::ManifestPath(_))
+    ));
+}
@@ -0,0 +1,115 @@
+use std::{fs, os::unix::fs::PermissionsExt};
+
+use pyxis_parcel::{ConfigPolicy, Drift, FileAdd, InodeAttr, InodeKind, Manifest, ParcelHandle};
+
+mod common;
+use common::Fixture;
+
+fn owned(perm: u32) -> InodeAttr {
+    InodeAttr {
+        perm,
+        uid: unsafe { libc::geteuid() },
+        gid: unsafe { libc::getegid() },
+        ..Default::default()
+    }
+}
+
+fn mtree_parcel(f: &Fixture) -> ParcelHandle {
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let etc = parcel
+        .add_directory(owned(libc::S_IFDIR | 0o755), Default::default())
+        .unwrap();
+    parcel
+        .insert_dirent(1, "etc".into(), etc, InodeKind::Directory)
+        .unwrap();
+    for name in ["motd", "my file"] {
+        let ino = parcel
+            .add_file(
+                FileAdd::Bytes(b"hello\n".to_vec()),
+                owned(libc::S_IFREG | 0o644),
+                Default::default(),
+            )
+            .unwrap();
+        parcel
+            .insert_dirent(etc, name.into(), ino, InodeKind::RegularFile)
+            .unwrap();
+    }
+    let link = parcel
+        .add_symlink(
+            "motd".into(),
+            owned(libc::S_IFLNK | 0o777),
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(etc, "issue".into(), link, InodeKind::Symlink)
+        .unwrap();
+    parcel.store().unwrap();
+    parcel
+}
+
+#[test]
+fn mtree_output() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = mtree_parcel(&f);
+    let mut out = Vec::new();
+    parcel.write_mtree(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let uid = unsafe { libc::geteuid() };
+    let gid = unsafe { libc::getegid() };
+    let digest = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+    assert_eq!(
+        out,
+        format!(
+            "#mtree\n\
+             . type=dir mode=0755 uid=0 gid=0\n\
+             ./etc type=dir mode=0755 uid={uid} gid={gid}\n\
+             ./etc/issue type=link uid={uid} gid={gid} link=motd\n\
+             ./etc/motd type=file mode=0644 uid={uid} gid={gid} size=6 sha256digest={digest}\n\
+             ./etc/my\\040file type=file mode=0644 uid={uid} gid={gid} size=6 sha256digest={digest}\n"
+        )
+    );
+
+    // The output doubles as an override manifest
+    let manifest = Manifest::parse(&out).unwrap();
+    assert_eq!(manifest.entries.len(), 5);
+    assert_eq!(
+        manifest.entries[4].path,
+        std::path::PathBuf::from("/etc/my file")
+    );
+}
+
+#[test]
+fn mtree_check() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = mtree_parcel(&f);
+    let root = tempfile::tempdir().unwrap();
+    parcel
+        .extract(root.path(), &ConfigPolicy::KeepExisting)
+        .unwrap();
+    assert_eq!(parcel.check_installed(root.path()).unwrap(), vec![]);
+
+    fs::write(root.path().join("etc/motd"), b"HELLO\n").unwrap();
+    fs::set_permissions(
+        root.path().join("etc/my file"),
+        fs::Permissions::from_mode(0o600),
+    )
+    .unwrap();
+    fs::remove_file(root.path().join("etc/issue")).unwrap();
+
+    let drift = parcel.check_installed(root.path()).unwrap();
+    assert_eq!(drift.len(), 3);
+    assert_eq!(drift[0], Drift::Missing("/etc/issue".into()));
+    assert!(matches!(
+        &drift[1],
+        Drift::Changed {
+            keyword: "sha256digest",
+            ..
+        }
+    ));
+    assert_eq!(
+        drift[2].to_string(),
+        "/etc/my file: mode (expected 0644, found 0600)"
+    );
+}
@@ -0,0 +1,97 @@
+use std::{
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use pyxis_parcel::{ConfigPolicy, FileAdd, ParcelHandle, SigningKey};
+
+mod common;
+use common::{add_file, sparse_input, stored_parcel, Fixture};
+
+const SIZE: u64 = 16 << 20;
+const OFFSET: u64 = 8 << 20;
+
+fn sparse_parcel(f: &Fixture, input: &Path) -> ParcelHandle {
+    stored_parcel(f, [("sparse", FileAdd::Name(input.into()))])
+}
+
+#[test]
+fn sparse_store_read() {
+    let input = tempfile::tempdir().unwrap();
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = sparse_parcel(&f, &sparse_input(input.path(), SIZE, OFFSET));
+
+    // Holes take no space in the data section
+    assert!(fs::metadata(PathBuf::from(&f)).unwrap().len() < 1 << 20);
+
+    let ino = parcel.select(PathBuf::from("/sparse")).unwrap();
+    assert_eq!(parcel.getattr(ino).unwrap().size, SIZE);
+    let segments = parcel.segments(ino).unwrap();
+    assert!(segments
+        .iter()
+        .any(|(start, len)| *start <= OFFSET && start + len >= OFFSET + 5));
+    assert_eq!(parcel.read(ino, 0, Some(4)).unwrap(), [0; 4]);
+    assert_eq!(
+        parcel.read(ino, OFFSET - 2, Some(9)).unwrap(),
+        b"\0\0hello\0\0"
+    );
+    assert_eq!(parcel.read(ino, SIZE - 4, Some(4)).unwrap(), [0; 4]);
+}
+
+#[test]
+fn sparse_extract() {
+    let input = tempfile::tempdir().unwrap();
+    let root = tempfile::tempdir().unwrap();
+    let f = Fixture::blank("test.parcel");
+    let source = sparse_input(input.path(), SIZE, OFFSET);
+    let mut parcel = sparse_parcel(&f, &source);
+
+    parcel
+        .extract(root.path(), &ConfigPolicy::KeepExisting)
+        .unwrap();
+    let dest = root.path().join("sparse");
+    assert_eq!(fs::read(&dest).unwrap(), fs::read(&source).unwrap());
+    // Holes are recreated rather than written out as zeros
+    assert!(fs::metadata(&dest).unwrap().blocks() * 512 < 1 << 20);
+}
+
+#[test]
+fn sparse_write_densifies() {
+    let input = tempfile::tempdir().unwrap();
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = sparse_parcel(&f, &sparse_input(input.path(), SIZE, OFFSET));
+    let ino = parcel.select(PathBuf::from("/sparse")).unwrap();
+
+    parcel.write(ino, 0, b"world").unwrap();
+    assert_eq!(parcel.segments(ino), None);
+    assert_eq!(parcel.read(ino, 0, Some(5)).unwrap(), b"world");
+    assert_eq!(parcel.read(ino, OFFSET, Some(5)).unwrap(), b"hello");
+    assert_eq!(parcel.read(ino, SIZE - 4, Some(4)).unwrap(), [0; 4]);
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.read(ino, 0, Some(5)).unwrap(), b"world");
+    assert_eq!(parcel.read(ino, OFFSET, Some(5)).unwrap(), b"hello");
+}
+
+#[test]
+fn dense_copy_of_signed_sparse() {
+    let input = tempfile::tempdir().unwrap();
+    let f = Fixture::blank("test.parcel");
+    let source = sparse_input(input.path(), SIZE, OFFSET);
+    let mut parcel = sparse_parcel(&f, &source);
+    // Signing gives the sparse file a digest, which a dense file with the same contents shares
+    parcel.sign(&SigningKey::from_bytes(&[1; 32])).unwrap();
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    let dense = add_file(&mut parcel, 1, "dense", &fs::read(&source).unwrap());
+    assert_eq!(parcel.dedup_savings().files, 0);
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.segments(dense), None);
+    assert_eq!(parcel.read(dense, OFFSET, Some(5)).unwrap(), b"hello");
+    assert_eq!(parcel.read(dense, SIZE - 4, Some(4)).unwrap(), [0; 4]);
+}
@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use pyxis_parcel::{ParcelHandle, BLOCK_SIZE};
+
+mod common;
+use common::{add_file, Fixture};
+
+const FILES: [(&str, &[u8]); 3] = [("a", b"a"), ("b", b"bb"), ("c", b"ccc")];
+
+fn assert_aligned(parcel: &mut ParcelHandle, name: &str, contents: &[u8]) {
+    let ino = parcel.select(PathBuf::from("/").join(name)).unwrap();
+    assert_eq!(parcel.data_position(ino).unwrap() % BLOCK_SIZE, 0);
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), contents);
+}
+
+#[test]
+fn aligned_store() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_alignment(Some(BLOCK_SIZE));
+    parcel.set_file(f.make_rw()).unwrap();
+    for (name, contents) in FILES {
+        add_file(&mut parcel, 1, name, contents);
+    }
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    for (name, contents) in FILES {
+        assert_aligned(&mut parcel, name, contents);
+    }
+
+    // The alignment is recorded in the header and applies to later additions and moves
+    add_file(&mut parcel, 1, "d", b"dddd");
+    let ino = parcel.select(PathBuf::from("/a")).unwrap();
+    parcel.realloc_reserved(ino, 16).unwrap();
+    parcel.store().unwrap();
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    for (name, contents) in FILES.into_iter().chain([("d", b"dddd" as &[u8])]) {
+        assert_aligned(&mut parcel, name, contents);
+    }
+}
+
+#[test]
+fn align_existing() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    add_file(&mut parcel, 1, "a", b"a");
+    parcel.store().unwrap();
+
+    // Files already stored keep their place; new ones are aligned within the file
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    let a = parcel.select(PathBuf::from("/a")).unwrap();
+    let position = parcel.data_position(a).unwrap();
+    assert_ne!(position % BLOCK_SIZE, 0);
+    parcel.set_alignment(Some(BLOCK_SIZE));
+    add_file(&mut parcel, 1, "b", b"bb");
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.data_position(a).unwrap(), position);
+    assert_eq!(parcel.read(a, 0, None).unwrap(), b"a");
+    assert_aligned(&mut parcel, "b", b"bb");
+}
+
+#[test]
+fn unaligned_packed() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    for (name, contents) in FILES {
+        add_file(&mut parcel, 1, name, contents);
+    }
+    parcel.store().unwrap();
+
+    let parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    let a = parcel.select(PathBuf::from("/a")).unwrap();
+    let b = parcel.select(PathBuf::from("/b")).unwrap();
+    assert_eq!(
+        parcel.data_position(b).unwrap(),
+        parcel.data_position(a).unwrap() + 1
+    );
+}
@@ -0,0 +1,59 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use pyxis_parcel::{FileAdd, ParcelHandle};
+
+mod common;
+use common::{patterned, stored_parcel, Fixture};
+
+const LEN: u64 = 300_000;
+
+fn big_parcel(f: &Fixture) -> (ParcelHandle, u64) {
+    let parcel = stored_parcel(f, [("big", FileAdd::Bytes(patterned(LEN)))]);
+    let ino = parcel.select(PathBuf::from("/big")).unwrap();
+    (parcel, ino)
+}
+
+#[test]
+fn copy_to_file() {
+    let f = Fixture::blank("test.parcel");
+    let dir = tempfile::tempdir().unwrap();
+    let (mut parcel, ino) = big_parcel(&f);
+
+    let dest = dir.path().join("big");
+    let copied = parcel
+        .copy_to(ino, 0, None, &mut File::create(&dest).unwrap())
+        .unwrap();
+    assert_eq!(copied, LEN);
+    assert_eq!(fs::read(&dest).unwrap(), patterned(LEN));
+
+    let copied = parcel
+        .copy_to(ino, 1000, Some(5000), &mut File::create(&dest).unwrap())
+        .unwrap();
+    assert_eq!(copied, 5000);
+    assert_eq!(fs::read(&dest).unwrap(), &patterned(LEN)[1000..6000]);
+
+    // Copying doesn't disturb later reads through the handle
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), patterned(LEN));
+}
+
+#[test]
+fn copy_to_socket() {
+    let f = Fixture::blank("test.parcel");
+    let (mut parcel, ino) = big_parcel(&f);
+
+    // copy_file_range can't write to sockets, so this goes through sendfile
+    let (mut tx, mut rx) = UnixStream::pair().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        rx.read_to_end(&mut buf).unwrap();
+        buf
+    });
+    parcel.copy_to(ino, 0, None, &mut tx).unwrap();
+    drop(tx);
+    assert_eq!(reader.join().unwrap(), patterned(LEN));
+}
@@ -0,0 +1,207 @@
+use std::{
+    cell::Cell,
+    fs::{self, File},
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use pyxis_parcel::{FileBacking, ParcelError, ParcelHandle, ReaderWriter};
+
+mod common;
+use common::{add_file, Fixture};
+
+/// Backing that fails every write once `budget` bytes have been written, as if the process died,
+/// and optionally fails to truncate
+struct Crashing {
+    inner:    ReaderWriter,
+    budget:   usize,
+    written:  Rc<Cell<usize>>,
+    truncate: bool,
+}
+
+impl Read for Crashing {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+impl BufRead for Crashing {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+impl Seek for Crashing {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(from)
+    }
+    fn stream_position(&mut self) -> io::Result<u64> {
+        self.inner.stream_position()
+    }
+}
+impl Write for Crashing {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.budget - self.written.get());
+        if n == 0 {
+            return Err(io::Error::other("crashed"));
+        }
+        let n = self.inner.write(&buf[..n])?;
+        self.written.set(self.written.get() + n);
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+impl FileBacking for Crashing {
+    fn file(&self) -> Option<&File> {
+        self.inner.file()
+    }
+    fn set_len(&mut self, len: u64) -> io::Result<bool> {
+        match self.truncate {
+            true => self.inner.set_len(len),
+            false => Err(io::Error::other("can't truncate")),
+        }
+    }
+}
+
+fn base_parcel(f: &Fixture) {
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let ino = add_file(&mut parcel, 1, "old", b"old contents");
+    parcel
+        .getxattrs_mut(ino)
+        .unwrap()
+        .insert("user.note".into(), vec![b'x'; 200]);
+    parcel.store().unwrap();
+}
+
+/// Interrupt `modify` and the store after it at many points, checking the parcel always loads
+/// as either the state before or the state after. Returns how many of each were seen.
+fn crash_everywhere(modify: fn(&mut ParcelHandle)) -> (usize, usize) {
+    let base = Fixture::blank("base.parcel");
+    base_parcel(&base);
+    let expected = |modified| {
+        let f = Fixture::blank("expected.parcel");
+        fs::copy(PathBuf::from(&base), PathBuf::from(&f)).unwrap();
+        let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+        if modified {
+            modify(&mut parcel);
+            parcel.store().unwrap();
+        }
+        snapshot(&mut ParcelHandle::load(f.make_rw()).unwrap())
+    };
+    let (before, after) = (expected(false), expected(true));
+
+    let crash = |budget| {
+        let f = Fixture::blank("test.parcel");
+        fs::copy(PathBuf::from(&base), PathBuf::from(&f)).unwrap();
+        let written = Rc::new(Cell::new(0));
+        let backing = Crashing {
+            inner: *f.make_rw(),
+            budget,
+            written: written.clone(),
+            truncate: true,
+        };
+        let mut parcel = ParcelHandle::load(Box::new(backing)).unwrap();
+        modify(&mut parcel);
+        let stored = parcel.store().is_ok();
+        drop(parcel);
+        let found = snapshot(&mut ParcelHandle::load(f.make_rw()).unwrap());
+        assert!(
+            found == before || found == after,
+            "crash after {} bytes left {:?}",
+            budget,
+            found
+        );
+        if stored {
+            assert_eq!(found, after);
+        }
+        (written.get(), found == after)
+    };
+
+    let (total, _) = crash(usize::MAX);
+    let mut seen = (0, 0);
+    for budget in (0..total).step_by(total / 300 + 1) {
+        match crash(budget).1 {
+            false => seen.0 += 1,
+            true => seen.1 += 1,
+        }
+    }
+    seen
+}
+
+fn snapshot(parcel: &mut ParcelHandle) -> Vec<(String, u32, usize, Vec<u8>)> {
+    let mut files = Vec::new();
+    for (ino, _, name) in parcel.readdir(1).unwrap() {
+        let uid = parcel.getattr(ino).unwrap().uid;
+        let xattrs = parcel.getxattrs(ino).unwrap().len();
+        files.push((name, uid, xattrs, parcel.read(ino, 0, None).unwrap()));
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn crash_growing_header() {
+    // Enough new records that the header outgrows its space and the data section moves
+    let (before, after) = crash_everywhere(|parcel| {
+        for i in 0..20 {
+            add_file(
+                parcel,
+                1,
+                &format!("new{}", i),
+                format!("new {}", i).as_bytes(),
+            );
+        }
+    });
+    assert!(before > 0 && after > 0);
+}
+
+#[test]
+fn crash_rewriting_header() {
+    // The header shrinks and is rewritten in place
+    let (before, after) = crash_everywhere(|parcel| {
+        let ino = parcel.select(PathBuf::from("/old")).unwrap();
+        parcel.getattr_mut(ino).unwrap().uid = 7;
+        parcel.getxattrs_mut(ino).unwrap().clear();
+    });
+    assert!(before > 0 && after > 0);
+}
+
+#[test]
+fn recover_needs_write_access() {
+    let f = Fixture::blank("test.parcel");
+    base_parcel(&f);
+    // The store is applied but its journal can't be cut off, so it stays for the next load
+    let backing = Crashing {
+        inner:    *f.make_rw(),
+        budget:   usize::MAX,
+        written:  Default::default(),
+        truncate: false,
+    };
+    let mut parcel = ParcelHandle::load(Box::new(backing)).unwrap();
+    add_file(&mut parcel, 1, "new", b"new contents");
+    assert!(parcel.store().is_err());
+    drop(parcel);
+
+    let read_only = || {
+        let file = File::open(PathBuf::from(&f)).unwrap();
+        ParcelHandle::load(Box::new(ReaderWriter::new(file)))
+    };
+    let err = read_only().err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::NeedsRecovery)
+    ));
+
+    let before = fs::metadata(PathBuf::from(&f)).unwrap().len();
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    let ino = parcel.select(PathBuf::from("/new")).unwrap();
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"new contents");
+    drop(parcel);
+    assert!(fs::metadata(PathBuf::from(&f)).unwrap().len() < before);
+    assert!(read_only().is_ok());
+}
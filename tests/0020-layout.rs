@@ -0,0 +1,54 @@
+use std::{fs, path::PathBuf};
+
+use pyxis_parcel::ParcelHandle;
+
+mod common;
+use common::{add_file, Fixture};
+
+#[test]
+fn header_growth_keeps_data() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let ino = add_file(&mut parcel, 1, "first", b"first contents");
+    parcel.store().unwrap();
+    let position = parcel.data_position(ino).unwrap() as usize;
+
+    // The header outgrows its space several times over
+    for round in 0..5 {
+        let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+        for i in 0..10 {
+            add_file(&mut parcel, 1, &format!("file{}-{}", round, i), b"more");
+        }
+        parcel.store().unwrap();
+    }
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.data_position(ino).unwrap() as usize, position);
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"first contents");
+    let stored = fs::read(PathBuf::from(&f)).unwrap();
+    assert_eq!(&stored[position..position + 14], b"first contents");
+    assert_eq!(parcel.readdir(1).unwrap().len(), 51);
+    let last = parcel.select(PathBuf::from("/file4-9")).unwrap();
+    assert_eq!(parcel.read(last, 0, None).unwrap(), b"more");
+}
+
+#[test]
+fn header_rewritten_in_place() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let ino = add_file(&mut parcel, 1, "file", b"contents");
+    parcel.getattr_mut(ino).unwrap().uid = 1000;
+    parcel.store().unwrap();
+    let len = fs::metadata(PathBuf::from(&f)).unwrap().len();
+
+    // A header that still fits in its slot doesn't take any more space
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    parcel.getattr_mut(ino).unwrap().uid = 0;
+    parcel.store().unwrap();
+    assert_eq!(fs::metadata(PathBuf::from(&f)).unwrap().len(), len);
+
+    let parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.getattr(ino).unwrap().uid, 0);
+}
@@ -0,0 +1,173 @@
+use std::{fs, path::PathBuf};
+
+use pyxis_parcel::{FileAdd, InodeKind, ParcelHandle, Transaction};
+
+mod common;
+use common::{add_file, stored_parcel, Fixture};
+
+fn add_in(txn: &mut Transaction, name: &str, contents: &[u8]) -> u64 {
+    let ino = txn
+        .add_file(
+            FileAdd::Bytes(contents.to_vec()),
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+    txn.insert_dirent(1, name.into(), ino, InodeKind::RegularFile)
+        .unwrap();
+    ino
+}
+
+/// A stored parcel holding `/file`, and that file's inode
+fn original_parcel(f: &Fixture) -> (ParcelHandle, u64) {
+    let parcel = stored_parcel(f, [("file", FileAdd::Bytes(b"original".to_vec()))]);
+    let ino = parcel.select(PathBuf::from("/file")).unwrap();
+    (parcel, ino)
+}
+
+#[test]
+fn rollback_discards_changes() {
+    let f = Fixture::blank("test.parcel");
+    let (mut parcel, ino) = original_parcel(&f);
+
+    let mut txn = parcel.begin();
+    add_in(&mut txn, "added", b"added");
+    txn.getattr_mut(ino).unwrap().uid = 7;
+    txn.getxattrs_mut(ino)
+        .unwrap()
+        .insert("user.test".into(), b"value".to_vec());
+    assert!(txn.select(PathBuf::from("/added")).is_some());
+    txn.rollback();
+
+    assert_eq!(parcel.select(PathBuf::from("/added")), None);
+    assert_eq!(parcel.getattr(ino).unwrap().uid, 0);
+    assert!(parcel.getxattrs(ino).unwrap().is_empty());
+    assert_eq!(parcel.readdir(1).unwrap().len(), 1);
+}
+
+#[test]
+fn commit_keeps_changes() {
+    let f = Fixture::blank("test.parcel");
+    let (mut parcel, ino) = original_parcel(&f);
+
+    let mut txn = parcel.begin();
+    let added = add_in(&mut txn, "added", b"added");
+    txn.getattr_mut(ino).unwrap().uid = 7;
+    txn.commit();
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.select(PathBuf::from("/added")), Some(added));
+    assert_eq!(parcel.read(added, 0, None).unwrap(), b"added");
+    assert_eq!(parcel.getattr(ino).unwrap().uid, 7);
+}
+
+#[test]
+fn rollback_restores_written_data() {
+    let f = Fixture::blank("test.parcel");
+    let (mut parcel, ino) = original_parcel(&f);
+    let position = parcel.data_position(ino).unwrap() as usize;
+
+    let mut txn = parcel.begin();
+    txn.write(ino, 0, b"modified").unwrap();
+    assert_eq!(txn.read(ino, 0, None).unwrap(), b"modified");
+    txn.rollback();
+
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"original");
+    assert_eq!(parcel.data_position(ino).unwrap() as usize, position);
+    let stored = fs::read(PathBuf::from(&f)).unwrap();
+    assert_eq!(&stored[position..position + 8], b"original");
+
+    // Space used by the rolled back write is reused
+    let mut txn = parcel.begin();
+    txn.write(ino, 0, b"modified").unwrap();
+    txn.commit();
+    parcel.store().unwrap();
+    let len = fs::metadata(PathBuf::from(&f)).unwrap().len();
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"modified");
+    let mut txn = parcel.begin();
+    txn.write(ino, 0, b"discard!").unwrap();
+    txn.rollback();
+    parcel.write(ino, 0, b"retained").unwrap();
+    parcel.store().unwrap();
+    assert_eq!(fs::metadata(PathBuf::from(&f)).unwrap().len(), len + 8);
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"retained");
+}
+
+#[test]
+fn rollback_restores_capacity() {
+    let f = Fixture::blank("test.parcel");
+    let (mut parcel, ino) = original_parcel(&f);
+    let position = parcel.data_position(ino);
+
+    let mut txn = parcel.begin();
+    txn.realloc_reserved(ino, 64).unwrap();
+    txn.expand_write(ino, 8, b" and more").unwrap();
+    txn.rollback();
+
+    assert_eq!(parcel.data_position(ino), position);
+    assert_eq!(parcel.getattr(ino).unwrap().size, 8);
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"original");
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"original");
+}
+
+#[test]
+fn drop_rolls_back() {
+    let f = Fixture::blank("test.parcel");
+    let (mut parcel, ino) = original_parcel(&f);
+
+    {
+        let mut txn = parcel.begin();
+        txn.write(ino, 0, b"modified").unwrap();
+        txn.delete(ino).unwrap();
+    }
+
+    assert_eq!(parcel.select(PathBuf::from("/file")), Some(ino));
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"original");
+}
+
+#[test]
+fn nested_rollback() {
+    let f = Fixture::blank("test.parcel");
+    let (mut parcel, ino) = original_parcel(&f);
+
+    let mut outer = parcel.begin();
+    outer.write(ino, 0, b"outer---").unwrap();
+    let mut inner = outer.begin();
+    inner.write(ino, 0, b"inner---").unwrap();
+    inner.rollback();
+    assert_eq!(outer.read(ino, 0, None).unwrap(), b"outer---");
+    outer.rollback();
+
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"original");
+}
+
+#[test]
+fn inner_commit_outer_rollback() {
+    let f = Fixture::blank("test.parcel");
+    let (mut parcel, ino) = original_parcel(&f);
+
+    let mut outer = parcel.begin();
+    let mut inner = outer.begin();
+    inner.write(ino, 0, b"inner---").unwrap();
+    add_in(&mut inner, "added", b"shared");
+    inner.commit();
+    outer.rollback();
+
+    assert_eq!(parcel.select(PathBuf::from("/added")), None);
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"original");
+    // The rolled back file's extent is gone, so identical contents are stored afresh
+    let again = add_file(&mut parcel, 1, "again", b"shared");
+    assert_eq!(parcel.dedup_savings().files, 0);
+    parcel.store().unwrap();
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.read(again, 0, None).unwrap(), b"shared");
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"original");
+}
@@ -0,0 +1,105 @@
+use std::{
+    env,
+    fs::File,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use pyxis_parcel::{ParcelError, ParcelHandle, ReaderWriter};
+
+mod common;
+use common::{stored_parcel, Fixture};
+
+// Locks only conflict between processes, so the other side of each test runs `child` in a
+// copy of this test binary, told what to do through the environment.
+const CHILD_PATH: &str = "PARCEL_LOCK_PATH";
+const CHILD_ACTION: &str = "PARCEL_LOCK_ACTION";
+
+fn spawn_child(path: &Path, action: &str) -> Child {
+    Command::new(env::current_exe().unwrap())
+        .args(["child", "--exact", "--quiet"])
+        .env(CHILD_PATH, path)
+        .env(CHILD_ACTION, action)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap()
+}
+
+fn child_succeeds(path: &Path, action: &str) -> bool {
+    spawn_child(path, action).wait().unwrap().success()
+}
+
+fn open(path: &Path, write: bool) -> Box<ReaderWriter> {
+    let f = File::options().read(true).write(write).open(path).unwrap();
+    Box::new(ReaderWriter::new(f))
+}
+
+fn assert_locked<T>(result: anyhow::Result<T>) {
+    let err = result.err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::Locked)
+    ));
+}
+
+#[test]
+fn child() {
+    let (path, action) = match (env::var(CHILD_PATH), env::var(CHILD_ACTION)) {
+        (Ok(path), Ok(action)) => (PathBuf::from(path), action),
+        // Run directly by the test harness rather than by another test
+        _ => return,
+    };
+    match action.as_str() {
+        "read" => drop(ParcelHandle::try_load(open(&path, false)).unwrap()),
+        "write" => drop(ParcelHandle::try_load(open(&path, true)).unwrap()),
+        "read-locked" => assert_locked(ParcelHandle::try_load(open(&path, false))),
+        "write-locked" => assert_locked(ParcelHandle::try_load(open(&path, true))),
+        "set-file-locked" => assert_locked(ParcelHandle::new().try_set_file(open(&path, true))),
+        "wait" => drop(ParcelHandle::load(open(&path, true)).unwrap()),
+        _ => panic!("Unknown action {}", action),
+    }
+}
+
+#[test]
+fn writer_excludes_others() {
+    let f = Fixture::blank("test.parcel");
+    stored_parcel::<&str>(&f, []);
+    let path = PathBuf::from(&f);
+
+    let parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert!(child_succeeds(&path, "read-locked"));
+    assert!(child_succeeds(&path, "write-locked"));
+    assert!(child_succeeds(&path, "set-file-locked"));
+
+    drop(parcel);
+    assert!(child_succeeds(&path, "write"));
+}
+
+#[test]
+fn readers_share() {
+    let f = Fixture::blank("test.parcel");
+    stored_parcel::<&str>(&f, []);
+    let path = PathBuf::from(&f);
+
+    let parcel = ParcelHandle::load(open(&path, false)).unwrap();
+    assert!(child_succeeds(&path, "read"));
+    assert!(child_succeeds(&path, "write-locked"));
+
+    drop(parcel);
+    assert!(child_succeeds(&path, "write"));
+}
+
+#[test]
+fn load_waits_for_writer() {
+    let f = Fixture::blank("test.parcel");
+    let parcel = stored_parcel::<&str>(&f, []);
+
+    let mut waiting = spawn_child(&PathBuf::from(&f), "wait");
+    thread::sleep(Duration::from_millis(200));
+    assert!(waiting.try_wait().unwrap().is_none());
+
+    drop(parcel);
+    assert!(waiting.wait().unwrap().success());
+}
@@ -0,0 +1,87 @@
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+    sync::Arc,
+    thread,
+};
+
+use pyxis_parcel::{FileAdd, ParcelError, ParcelHandle, ParcelView};
+
+mod common;
+use common::{add_file, stored_parcel, Fixture};
+
+fn contents(i: usize) -> Vec<u8> {
+    format!("file {} ", i).repeat(1000 + i).into_bytes()
+}
+
+/// `count` files named after their numbers
+fn files(count: usize) -> impl Iterator<Item = (String, FileAdd)> {
+    (0..count).map(|i| (format!("file{}", i), FileAdd::Bytes(contents(i))))
+}
+
+#[test]
+fn view_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<ParcelView>();
+}
+
+#[test]
+fn concurrent_reads() {
+    let f = Fixture::blank("test.parcel");
+    stored_parcel(&f, files(8));
+    let view = Arc::new(
+        ParcelHandle::load(f.make_rw())
+            .unwrap()
+            .into_view()
+            .unwrap(),
+    );
+
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let view = Arc::clone(&view);
+            thread::spawn(move || {
+                let ino = view.select(PathBuf::from(format!("/file{}", i))).unwrap();
+                let expected = contents(i);
+                for round in 0..100 {
+                    let offset = (round * 7) as u64;
+                    let data = view.read(ino, offset, Some(64)).unwrap();
+                    assert_eq!(data, expected[offset as usize..offset as usize + 64]);
+                }
+                assert_eq!(view.read(ino, 0, None).unwrap(), expected);
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn view_metadata_and_copy() {
+    let f = Fixture::blank("test.parcel");
+    let view = stored_parcel(&f, files(2)).into_view().unwrap();
+
+    assert_eq!(view.readdir(1).unwrap().len(), 2);
+    let ino = view.lookup(1, "file1").unwrap();
+    assert_eq!(view.getattr(ino).unwrap().size, contents(1).len() as u64);
+
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("out");
+    let copied = view
+        .copy_to(ino, 0, None, &mut File::create(&dest).unwrap())
+        .unwrap();
+    assert_eq!(copied, contents(1).len() as u64);
+    assert_eq!(fs::read(&dest).unwrap(), contents(1));
+}
+
+#[test]
+fn view_needs_store() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = stored_parcel(&f, files(1));
+    add_file(&mut parcel, 1, "unstored", b"unstored");
+    let err = parcel.into_view().err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::NotStored)
+    ));
+}
@@ -0,0 +1,105 @@
+#![cfg(feature = "tokio")]
+
+use std::{
+    fs::File,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use pyxis_parcel::{AsyncParcel, FileAdd, ParcelError};
+use tokio::io::AsyncReadExt;
+
+mod common;
+use common::{patterned, sparse_input, stored_parcel, Fixture};
+
+const SIZE: u64 = 1 << 20;
+
+/// Drop the parcel from the page cache, so reads have to wait for the disk
+fn evict(path: &Path) {
+    let file = File::open(path).unwrap();
+    file.sync_all().unwrap();
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+}
+
+async fn load(f: &Fixture) -> (AsyncParcel, u64, u64) {
+    let input = tempfile::tempdir().unwrap();
+    let sparse = sparse_input(input.path(), 4 * SIZE, 2 * SIZE);
+    stored_parcel(
+        f,
+        [
+            ("file", FileAdd::Bytes(patterned(SIZE))),
+            ("sparse", FileAdd::Name(sparse.into())),
+        ],
+    );
+    let parcel = AsyncParcel::load(PathBuf::from(f)).await.unwrap();
+    let file = parcel.view().select(PathBuf::from("/file")).unwrap();
+    let sparse = parcel.view().select(PathBuf::from("/sparse")).unwrap();
+    (parcel, file, sparse)
+}
+
+#[tokio::test]
+async fn async_read() {
+    let f = Fixture::blank("test.parcel");
+    let (parcel, ino, sparse) = load(&f).await;
+
+    assert_eq!(parcel.read(ino, 0, None).await.unwrap(), patterned(SIZE));
+    assert_eq!(
+        parcel.read(ino, 1000, Some(10)).await.unwrap(),
+        patterned(SIZE)[1000..1010]
+    );
+    assert_eq!(
+        parcel.read(sparse, 2 * SIZE - 2, Some(9)).await.unwrap(),
+        b"\0\0hello\0\0"
+    );
+
+    evict(&PathBuf::from(&f));
+    assert_eq!(parcel.read(ino, 0, None).await.unwrap(), patterned(SIZE));
+}
+
+#[tokio::test]
+async fn async_stream() {
+    let f = Fixture::blank("test.parcel");
+    let (parcel, ino, sparse) = load(&f).await;
+
+    let mut buf = Vec::new();
+    parcel
+        .open(ino)
+        .unwrap()
+        .read_to_end(&mut buf)
+        .await
+        .unwrap();
+    assert_eq!(buf, patterned(SIZE));
+
+    evict(&PathBuf::from(&f));
+    let mut buf = Vec::new();
+    parcel
+        .open(ino)
+        .unwrap()
+        .read_to_end(&mut buf)
+        .await
+        .unwrap();
+    assert_eq!(buf, patterned(SIZE));
+
+    let mut buf = Vec::new();
+    parcel
+        .open(sparse)
+        .unwrap()
+        .read_to_end(&mut buf)
+        .await
+        .unwrap();
+    assert_eq!(buf.len() as u64, 4 * SIZE);
+    assert_eq!(&buf[2 * SIZE as usize..2 * SIZE as usize + 5], b"hello");
+    assert!(buf[..2 * SIZE as usize].iter().all(|b| *b == 0));
+}
+
+#[tokio::test]
+async fn async_open_directory() {
+    let f = Fixture::blank("test.parcel");
+    let (parcel, _, _) = load(&f).await;
+
+    let err = parcel.open(1).err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::NotFile)
+    ));
+}
@@ -0,0 +1,125 @@
+#![cfg(feature = "http")]
+
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use pyxis_parcel::{
+    FileAdd, HttpRange, ParcelError, ParcelHandle, RandomAccess, RandomAccessBacking,
+};
+
+mod common;
+use common::{stored_parcel, Fixture};
+
+const LARGE: usize = 1 << 20;
+
+/// A file too large to download for a small read, and a small one
+fn files() -> [(&'static str, FileAdd); 2] {
+    [
+        ("large", FileAdd::Bytes(vec![b'x'; LARGE])),
+        ("small", FileAdd::Bytes(b"hello".to_vec())),
+    ]
+}
+
+/// Serve `data` over HTTP on a local port, honouring range requests unless `ranges` is false.
+/// Returns the URL and the number of bytes sent so far.
+fn serve(data: Vec<u8>, ranges: bool) -> (String, Arc<Mutex<usize>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/test.parcel", listener.local_addr().unwrap());
+    let sent = Arc::new(Mutex::new(0));
+    let counter = Arc::clone(&sent);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut lines = BufReader::new(&stream).lines();
+            let request = lines.next().unwrap().unwrap();
+            let mut range = None;
+            for line in lines.map(|l| l.unwrap()).take_while(|l| !l.is_empty()) {
+                if let Some(spec) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                    let (start, end) = spec.split_once('-').unwrap();
+                    range = Some((start.parse().unwrap(), end.parse::<usize>().unwrap() + 1));
+                }
+            }
+            let (status, body) = match range {
+                Some((start, end)) if ranges => (
+                    format!(
+                        "206 Partial Content\r\nContent-Range: bytes {}-{}/{}",
+                        start,
+                        end - 1,
+                        data.len()
+                    ),
+                    &data[start..end.min(data.len())],
+                ),
+                _ => ("200 OK".to_string(), &data[..]),
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            )
+            .unwrap();
+            if !request.starts_with("HEAD") {
+                stream.write_all(body).unwrap();
+                *counter.lock().unwrap() += body.len();
+            }
+        }
+    });
+    (url, sent)
+}
+
+#[test]
+fn http_fetches_needed_ranges() {
+    let f = Fixture::blank("test.parcel");
+    stored_parcel(&f, files());
+    let (url, sent) = serve(fs::read(PathBuf::from(&f)).unwrap(), true);
+
+    let backing = RandomAccessBacking::new(HttpRange::new(&url).unwrap()).unwrap();
+    let mut parcel = ParcelHandle::load(Box::new(backing)).unwrap();
+    let ino = parcel.select(PathBuf::from("/small")).unwrap();
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"hello");
+    assert_eq!(parcel.readdir(1).unwrap().len(), 2);
+
+    // Neither the large file nor the whole parcel was downloaded
+    assert!(*sent.lock().unwrap() < LARGE / 4);
+
+    let ino = parcel.select(PathBuf::from("/large")).unwrap();
+    assert_eq!(
+        parcel.read(ino, LARGE as u64 - 4, Some(4)).unwrap(),
+        b"xxxx"
+    );
+}
+
+#[test]
+fn http_requires_ranges() {
+    let f = Fixture::blank("test.parcel");
+    stored_parcel(&f, files());
+    let (url, _) = serve(fs::read(PathBuf::from(&f)).unwrap(), false);
+
+    let source = HttpRange::new(&url).unwrap();
+    let err = source.read_at(&mut [0; 4], 0).err().unwrap();
+    assert!(matches!(
+        err.get_ref().and_then(|e| e.downcast_ref::<ParcelError>()),
+        Some(ParcelError::RangesUnsupported)
+    ));
+}
+
+#[test]
+fn local_random_access() {
+    let f = Fixture::blank("test.parcel");
+    stored_parcel(&f, files());
+
+    let source = File::open(PathBuf::from(&f)).unwrap();
+    let mut parcel =
+        ParcelHandle::load(Box::new(RandomAccessBacking::new(source).unwrap())).unwrap();
+    let ino = parcel.select(PathBuf::from("/small")).unwrap();
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"hello");
+    // The backing is read-only
+    parcel.getattr_mut(ino).unwrap().uid = 7;
+    assert!(parcel.store().is_err());
+}
@@ -0,0 +1,110 @@
+use std::{ffi::OsString, fs, path::PathBuf};
+
+use pyxis_parcel::{InodeKind, ParcelError, ParcelHandle};
+
+mod common;
+use common::Fixture;
+
+/// Check the contents of a fixture, written by an earlier version with the same tree
+fn check_contents(parcel: &mut ParcelHandle) {
+    let conf = parcel.select(PathBuf::from("/etc/tool.conf")).unwrap();
+    assert_eq!(parcel.read(conf, 0, None).unwrap(), b"setting = 1\n");
+    assert_eq!(parcel.getattr(conf).unwrap().uid, 1000);
+    assert_eq!(
+        parcel.getxattrs(conf).unwrap()[&OsString::from("user.note")],
+        b"kept"
+    );
+
+    let tool = parcel.select(PathBuf::from("/bin/tool")).unwrap();
+    assert_eq!(
+        parcel.read(tool, 0, None).unwrap(),
+        b"#!/bin/sh\necho tool\n"
+    );
+
+    let link = parcel.select(PathBuf::from("/link")).unwrap();
+    assert_eq!(parcel.readlink(link).unwrap(), b"bin/tool");
+
+    let null = parcel.select(PathBuf::from("/null")).unwrap();
+    assert_eq!(parcel.getattr(null).unwrap().rdev, 0x103);
+
+    let mut names: Vec<_> = parcel
+        .readdir(1)
+        .unwrap()
+        .into_iter()
+        .map(|(_, kind, name)| (name, kind))
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            ("bin".to_string(), InodeKind::Directory),
+            ("etc".to_string(), InodeKind::Directory),
+            ("link".to_string(), InodeKind::Symlink),
+            ("null".to_string(), InodeKind::CharDevice),
+        ]
+    );
+
+    let metadata = parcel.metadata();
+    assert_eq!(metadata.version, "1.2.3");
+    assert_eq!(metadata.depends, ["libc"]);
+    assert!(metadata.config_files.contains("/etc/tool.conf"));
+}
+
+fn load_and_upgrade(fixture: &str, version: u32) {
+    let f = Fixture::copy(fixture);
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.upgraded_from(), Some(version));
+    check_contents(&mut parcel);
+
+    parcel.store().unwrap();
+    assert_eq!(parcel.upgraded_from(), None);
+    drop(parcel);
+    assert!(fs::read(PathBuf::from(&f)).unwrap().starts_with(b"413\n@"));
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.upgraded_from(), None);
+    check_contents(&mut parcel);
+}
+
+#[test]
+fn upgrade_v2() {
+    load_and_upgrade("v2.parcel", 2);
+}
+
+#[test]
+fn upgrade_v3() {
+    load_and_upgrade("v3.parcel", 3);
+}
+
+#[test]
+fn upgraded_parcel_can_grow() {
+    let f = Fixture::copy("v2.parcel");
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    let tool = parcel.select(PathBuf::from("/bin/tool")).unwrap();
+    parcel.getattr_mut(tool).unwrap().uid = 7;
+    parcel.store().unwrap();
+    drop(parcel);
+
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    let tool = parcel.select(PathBuf::from("/bin/tool")).unwrap();
+    assert_eq!(parcel.getattr(tool).unwrap().uid, 7);
+    check_contents(&mut parcel);
+}
+
+#[test]
+fn unsupported_version() {
+    let f = Fixture::copy("v2.parcel");
+    let path = PathBuf::from(&f);
+    let old = fs::read(&path).unwrap();
+    let text = String::from_utf8(old).unwrap();
+    fs::write(&path, text.replacen("version: 2", "version: 1", 1)).unwrap();
+
+    let err = ParcelHandle::load(f.make_rw()).err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<ParcelError>(),
+        Some(ParcelError::VersionMismatch {
+            expected: 4,
+            found:    1,
+        })
+    ));
+}
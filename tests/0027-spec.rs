@@ -0,0 +1,209 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use pyxis_parcel::{validate, InodeKind, ParcelHandle, Violation};
+
+mod common;
+use common::{add_file, Fixture};
+
+/// One golden parcel per format version, all holding the same tree
+const GOLDEN: [&str; 3] = ["v2.parcel", "v3.parcel", "v4.parcel"];
+
+fn violations(path: &Path) -> Vec<Violation> {
+    validate(&mut File::open(path).unwrap()).unwrap()
+}
+
+/// Everything a parcel holds apart from its signature, one line per inode
+fn dump(parcel: &mut ParcelHandle) -> Vec<String> {
+    let mut lines = vec![format!("{:?}", parcel.metadata())];
+    for (ino, kind, path) in parcel.walk(1).unwrap() {
+        let attr = parcel.getattr(ino).unwrap();
+        let contents = match kind {
+            InodeKind::RegularFile => parcel.read(ino, 0, None).unwrap(),
+            InodeKind::Symlink => parcel.readlink(ino).unwrap(),
+            _ => Vec::new(),
+        };
+        lines.push(format!(
+            "{} {:?} {:o} {}:{} {} {} {:?} {:?}",
+            path.display(),
+            kind,
+            attr.perm,
+            attr.uid,
+            attr.gid,
+            attr.rdev,
+            attr.size,
+            parcel.getxattrs(ino).unwrap(),
+            contents
+        ));
+    }
+    lines
+}
+
+/// Rewrite the index of a header to match its records
+fn reindex(text: &str) -> String {
+    let (head, records) = text.split_once("records:\n").unwrap();
+    let (summary, index) = head.split_once("index: |\n").unwrap();
+    let mut starts: Vec<usize> = records
+        .match_indices("\n  - ")
+        .map(|(i, _)| i + 1)
+        .collect();
+    starts.insert(0, 0);
+    starts.push(records.len());
+    let index: String = index
+        .lines()
+        .zip(starts.windows(2))
+        .map(|(line, span)| {
+            format!(
+                "{} {:016x} {:08x}\n",
+                &line[..18],
+                span[0],
+                span[1] - span[0]
+            )
+        })
+        .collect();
+    format!("{}index: |\n{}records:\n{}", summary, index, records)
+}
+
+/// Replace text in the header of a current parcel, keeping its layout line and, if asked, its
+/// index in step
+fn patch_header(path: &Path, from: &str, to: &str, keep_index: bool) {
+    let mut bytes = fs::read(path).unwrap();
+    let field = |n: usize| {
+        let start = 5 + n * 17;
+        usize::from_str_radix(std::str::from_utf8(&bytes[start..start + 16]).unwrap(), 16).unwrap()
+    };
+    let (header, len, capacity) = (field(1), field(2), field(3));
+    let text = std::str::from_utf8(&bytes[header..header + len]).unwrap();
+    assert!(text.contains(from));
+    let mut patched = text.replacen(from, to, 1);
+    if keep_index {
+        patched = reindex(&patched);
+    }
+    let mut patched = patched.into_bytes();
+    let new_len = patched.len();
+    patched.extend_from_slice(b"\n...\n");
+    assert!(patched.len() <= capacity);
+    patched.resize(capacity, b' ');
+    bytes[header..header + capacity].copy_from_slice(&patched);
+    bytes[5 + 2 * 17..5 + 2 * 17 + 16].copy_from_slice(format!("{:016x}", new_len).as_bytes());
+    fs::write(path, bytes).unwrap();
+}
+
+/// Validate the current golden parcel after replacing text in its header
+fn patched_violations(from: &str, to: &str) -> Vec<Violation> {
+    let f = Fixture::copy("v4.parcel");
+    patch_header(&PathBuf::from(&f), from, to, true);
+    violations(&PathBuf::from(&f))
+}
+
+#[test]
+fn golden_parcels_are_valid() {
+    for golden in GOLDEN {
+        let f = Fixture::copy(golden);
+        assert_eq!(violations(&PathBuf::from(&f)), [], "{}", golden);
+    }
+}
+
+#[test]
+fn golden_parcels_load_identically() {
+    let dumps: Vec<_> = GOLDEN
+        .iter()
+        .map(|golden| {
+            let f = Fixture::copy(golden);
+            dump(&mut ParcelHandle::load(f.make_rw()).unwrap())
+        })
+        .collect();
+    assert_eq!(dumps[0].len(), 7);
+    assert_eq!(dumps[0], dumps[1]);
+    assert_eq!(dumps[0], dumps[2]);
+}
+
+#[test]
+fn stored_parcels_are_valid() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    // Identical files share an extent, which isn't an overlap
+    add_file(&mut parcel, 1, "a", b"same");
+    add_file(&mut parcel, 1, "b", b"same");
+    parcel.store().unwrap();
+    assert_eq!(parcel.dedup_savings().files, 1);
+    assert_eq!(violations(&PathBuf::from(&f)), []);
+}
+
+#[test]
+fn detects_bad_framing() {
+    let f = Fixture::copy("v4.parcel");
+    let path = PathBuf::from(&f);
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[0] = b'5';
+    fs::write(&path, &bytes).unwrap();
+    assert_eq!(violations(&path), [Violation::BadMagic]);
+
+    let f = Fixture::copy("v2.parcel");
+    let path = PathBuf::from(&f);
+    let text = fs::read_to_string(&path).unwrap();
+    fs::write(&path, text.replacen("\n...\n", "\n", 1)).unwrap();
+    assert_eq!(violations(&path), [Violation::Unterminated]);
+
+    assert_eq!(
+        patched_violations("version: 4", "version: 9"),
+        [Violation::UnsupportedVersion(9)]
+    );
+}
+
+#[test]
+fn detects_bad_index() {
+    let f = Fixture::copy("v4.parcel");
+    let path = PathBuf::from(&f);
+    patch_header(&path, "offset: 12\n", "offset: 120\n", false);
+    let found = violations(&path);
+    assert!(found.contains(&Violation::BadIndex { entry: 5 }));
+    assert!(!found.contains(&Violation::BadIndex { entry: 4 }));
+}
+
+#[test]
+fn detects_bad_dirents() {
+    assert_eq!(
+        patched_violations("- 6\n          - Symlink", "- 9\n          - Symlink"),
+        [
+            Violation::DanglingDirent {
+                dir:  1,
+                name: "link".into(),
+                ino:  9,
+            },
+            Violation::Orphan { ino: 6 },
+        ]
+    );
+    assert_eq!(
+        patched_violations("- 6\n          - Symlink", "- 6\n          - RegularFile"),
+        [Violation::DirentKind {
+            dir:      1,
+            name:     "link".into(),
+            recorded: InodeKind::RegularFile,
+            actual:   InodeKind::Symlink,
+        }]
+    );
+}
+
+#[test]
+fn detects_bad_extents() {
+    assert_eq!(
+        patched_violations("offset: 12\n", "offset: 10\n"),
+        [Violation::ExtentOverlap { ino: 4, other: 5 }]
+    );
+    assert_eq!(
+        patched_violations("offset: 12\n", "offset: 4176\n"),
+        [Violation::HeaderOverlap { ino: 5 }]
+    );
+    assert_eq!(
+        patched_violations("offset: 12\n", "offset: 99999999\n"),
+        [Violation::OutOfBounds { ino: 5 }]
+    );
+    assert_eq!(
+        patched_violations("capacity: 20\n", "capacity: 2\n"),
+        [Violation::SizeExceedsCapacity { ino: 5 }]
+    );
+}
@@ -0,0 +1,175 @@
+use std::{fs::File, path::PathBuf};
+
+use pyxis_parcel::{validate, FileAdd, InodeKind, ParcelHandle, Violation};
+
+mod common;
+use common::{add_dir, add_file, Fixture};
+
+/// Repair, store and reload a parcel, checking nothing is left to repair
+fn repair_and_reload(f: &Fixture, mut parcel: ParcelHandle) -> ParcelHandle {
+    assert_eq!(parcel.repair().unwrap(), []);
+    parcel.store().unwrap();
+    drop(parcel);
+    let path = PathBuf::from(f);
+    assert_eq!(validate(&mut File::open(path).unwrap()).unwrap(), []);
+    let mut parcel = ParcelHandle::load(f.make_rw()).unwrap();
+    assert_eq!(parcel.check().unwrap(), []);
+    parcel
+}
+
+#[test]
+fn consistent_parcel() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let dir = add_dir(&mut parcel, 1, "dir");
+    add_file(&mut parcel, dir, "file", b"hello");
+    assert_eq!(parcel.check().unwrap(), []);
+    parcel.store().unwrap();
+    assert_eq!(parcel.check().unwrap(), []);
+}
+
+#[test]
+fn deleted_file_leaves_dangling_dirent() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let ino = add_file(&mut parcel, 1, "file", b"hello");
+    add_file(&mut parcel, 1, "kept", b"kept");
+    parcel.store().unwrap();
+    parcel.delete(ino).unwrap();
+    assert_eq!(
+        parcel.check().unwrap(),
+        [Violation::DanglingDirent {
+            dir: 1,
+            name: "file".into(),
+            ino,
+        }]
+    );
+
+    let parcel = repair_and_reload(&f, parcel);
+    assert_eq!(parcel.readdir(1).unwrap().len(), 1);
+}
+
+#[test]
+fn orphans_move_to_lost_found() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let dir = add_dir(&mut parcel, 1, "dir");
+    let sub = add_dir(&mut parcel, dir, "sub");
+    let file = add_file(&mut parcel, sub, "file", b"hello");
+    let loose = parcel
+        .add_file(
+            FileAdd::Bytes(b"loose".to_vec()),
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+    parcel.store().unwrap();
+    parcel.delete(dir).unwrap();
+    let found = parcel.check().unwrap();
+    assert!(found.contains(&Violation::Orphan { ino: sub }));
+    assert!(found.contains(&Violation::Orphan { ino: file }));
+    assert!(found.contains(&Violation::Orphan { ino: loose }));
+
+    let mut parcel = repair_and_reload(&f, parcel);
+    let path = format!("/lost+found/#{}/file", sub);
+    let ino = parcel.select(PathBuf::from(path)).unwrap();
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"hello");
+    let ino = parcel
+        .select(PathBuf::from(format!("/lost+found/#{}", loose)))
+        .unwrap();
+    assert_eq!(parcel.read(ino, 0, None).unwrap(), b"loose");
+    // Only the top of the orphaned tree is linked
+    let lost_found = parcel.select(PathBuf::from("/lost+found")).unwrap();
+    assert_eq!(parcel.readdir(lost_found).unwrap().len(), 2);
+}
+
+#[test]
+fn wrong_links() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let a = add_dir(&mut parcel, 1, "a");
+    let b = add_dir(&mut parcel, 1, "b");
+    let file = add_file(&mut parcel, a, "file", b"hello");
+    // A second link, recorded with the wrong kind, makes `b` the parent
+    parcel
+        .insert_dirent(b, "link".into(), file, InodeKind::Symlink)
+        .unwrap();
+    parcel.getattr_mut(file).unwrap().nlink = 1;
+    let found = parcel.check().unwrap();
+    assert_eq!(
+        found,
+        [
+            Violation::DirentKind {
+                dir:      b,
+                name:     "link".into(),
+                recorded: InodeKind::Symlink,
+                actual:   InodeKind::RegularFile,
+            },
+            Violation::WrongNlink {
+                ino:      file,
+                recorded: 1,
+                expected: 2,
+            },
+        ]
+    );
+
+    // Deleting `b` leaves the file's parent pointing at it
+    parcel.delete(b).unwrap();
+    assert!(parcel.check().unwrap().contains(&Violation::WrongParent {
+        ino:      file,
+        recorded: b,
+        expected: a,
+    }));
+
+    let mut parcel = repair_and_reload(&f, parcel);
+    let attr = parcel.getattr(file).unwrap();
+    assert_eq!(attr.nlink, 1);
+    assert_eq!(parcel.read(file, 0, None).unwrap(), b"hello");
+}
+
+#[test]
+fn hardlinks_are_counted() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let dir = add_dir(&mut parcel, 1, "dir");
+    let file = add_file(&mut parcel, 1, "file", b"hello");
+    let link = parcel.add_hardlink("/file".into()).unwrap();
+    parcel
+        .insert_dirent(dir, "link".into(), link, InodeKind::RegularFile)
+        .unwrap();
+    assert_eq!(parcel.getattr(file).unwrap().nlink, 2);
+    assert_eq!(parcel.check().unwrap(), []);
+
+    // Replacing either entry drops its link
+    add_file(&mut parcel, dir, "link", b"other");
+    assert_eq!(parcel.getattr(file).unwrap().nlink, 1);
+    parcel.insert_whiteout(1, "file".into()).unwrap();
+    assert_eq!(parcel.getattr(file).unwrap().nlink, 0);
+    parcel.store().unwrap();
+    assert_eq!(parcel.check().unwrap(), [Violation::Orphan { ino: file }]);
+}
+
+#[test]
+fn data_problems_are_left() {
+    let f = Fixture::blank("test.parcel");
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    let ino = add_file(&mut parcel, 1, "file", b"hello");
+    parcel.store().unwrap();
+
+    // Lose all but the start of the data section, and with it the end of the file
+    File::options()
+        .write(true)
+        .open(PathBuf::from(&f))
+        .unwrap()
+        .set_len(4 + 69 + 2)
+        .unwrap();
+    let expected = [Violation::OutOfBounds { ino }];
+    assert_eq!(parcel.check().unwrap(), expected);
+    assert_eq!(parcel.repair().unwrap(), expected);
+}
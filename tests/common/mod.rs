@@ -1,15 +1,19 @@
+// Each test uses only some of these helpers
+#![allow(dead_code)]
+
 use std::{
     env,
     fs::{self, File},
-    path::PathBuf,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
 };
 
 use pretty_assertions::assert_eq;
-use pyxis_parcel::ReaderWriter;
+use pyxis_parcel::{FileAdd, InodeKind, ParcelHandle, ReaderWriter};
 use tempfile::TempDir;
 pub struct Fixture {
     path:     PathBuf,
-    _source:  PathBuf,
+    source:   PathBuf,
     _tempdir: TempDir,
 }
 
@@ -28,7 +32,7 @@ impl Fixture {
 
         Fixture {
             _tempdir: tempdir,
-            _source: source,
+            source,
             path,
         }
     }
@@ -44,11 +48,11 @@ impl Fixture {
         Box::new(ReaderWriter::new(f))
     }
 
-    //pub fn copy(fixture_filename: &str) -> Self {
-    //    let fixture = Fixture::blank(fixture_filename);
-    //    fs::copy(&fixture.source, &fixture.path).unwrap();
-    //    fixture
-    //}
+    pub fn copy(fixture_filename: &str) -> Self {
+        let fixture = Fixture::blank(fixture_filename);
+        fs::copy(&fixture.source, &fixture.path).unwrap();
+        fixture
+    }
 
     pub fn compare(&self, expected: &str) {
         let _ = fs::copy(PathBuf::from(self), "found.parcel");
@@ -89,3 +93,65 @@ impl From<&Fixture> for PathBuf {
         f.path.to_owned()
     }
 }
+
+/// Add a file holding `contents` to the directory `dir`
+pub fn add_file(parcel: &mut ParcelHandle, dir: u64, name: &str, contents: &[u8]) -> u64 {
+    let ino = parcel
+        .add_file(
+            FileAdd::Bytes(contents.to_vec()),
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+    parcel
+        .insert_dirent(dir, name.into(), ino, InodeKind::RegularFile)
+        .unwrap();
+    ino
+}
+
+/// Add an empty directory to the directory `dir`
+pub fn add_dir(parcel: &mut ParcelHandle, dir: u64, name: &str) -> u64 {
+    let ino = parcel
+        .add_directory(Default::default(), Default::default())
+        .unwrap();
+    parcel
+        .insert_dirent(dir, name.into(), ino, InodeKind::Directory)
+        .unwrap();
+    ino
+}
+
+/// Store a parcel holding `files` at its root, and load it back
+pub fn stored_parcel<N: AsRef<str>>(
+    f: &Fixture,
+    files: impl IntoIterator<Item = (N, FileAdd)>,
+) -> ParcelHandle {
+    let mut parcel = ParcelHandle::new();
+    parcel.set_file(f.make_rw()).unwrap();
+    for (name, from) in files {
+        let ino = parcel
+            .add_file(from, Default::default(), Default::default())
+            .unwrap();
+        parcel
+            .insert_dirent(1, name.as_ref().into(), ino, InodeKind::RegularFile)
+            .unwrap();
+    }
+    parcel.store().unwrap();
+    // Closing any descriptor for the file gives up the process's lock, so the parcel that
+    // stored it goes before the one returned takes the lock
+    drop(parcel);
+    ParcelHandle::load(f.make_rw()).unwrap()
+}
+
+/// Create a sparse file `size` bytes long in `dir`, holding nothing but `hello` at `offset`
+pub fn sparse_input(dir: &Path, size: u64, offset: u64) -> PathBuf {
+    let path = dir.join("sparse");
+    let file = File::create(&path).unwrap();
+    file.set_len(size).unwrap();
+    file.write_all_at(b"hello", offset).unwrap();
+    path
+}
+
+/// `len` bytes counting up modulo a prime, so data read from the wrong offset doesn't match
+pub fn patterned(len: u64) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
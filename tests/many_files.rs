@@ -11,7 +11,7 @@ use common::Fixture;
 fn many_file_roundtrip() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
 
     let mut rng = Pcg64::seed_from_u64(0);
     for i in 0..100 {
@@ -52,7 +52,7 @@ fn many_file_roundtrip() {
 fn tree() {
     let f = Fixture::blank("test.parcel");
     let mut parcel = ParcelHandle::new();
-    parcel.set_file(f.make_rw());
+    parcel.set_file(f.make_rw()).unwrap();
 
     let mut rng = Pcg64::seed_from_u64(2);
 
@@ -62,7 +62,9 @@ fn tree() {
         let n_children = rng.gen_range(0..8 - depth);
         println!("Adding {} children at depth {}", n_children, depth);
         for _ in 0..n_children {
-            let next = parcel.add_directory(Default::default(), Default::default());
+            let next = parcel
+                .add_directory(Default::default(), Default::default())
+                .unwrap();
 
             let name = (&mut rng)
                 .sample_iter(&Alphanumeric)